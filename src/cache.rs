@@ -1,16 +1,37 @@
+use std::collections::HashMap;
 use std::env::current_dir;
-use std::fs::File;
+use std::fs::{self, File};
 use std::hash::{DefaultHasher, Hash, Hasher};
-use std::path::PathBuf;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use anyhow::Result;
+use filetime::FileTime;
 use homedir::get_my_home;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use walkdir::WalkDir;
+
+use crate::utils::update_timestamps;
 
 #[derive(Serialize, Deserialize, Clone, Default)]
 pub struct Cache {
     pub last_executed_profile: Option<String>,
+    /// What was found at each path touched by a profile in the last run
+    ///
+    /// The state is None if the path was empty (e.g. a hidden file).
+    #[serde(default)]
+    pub profiled_paths: HashMap<PathBuf, Option<PathState>>,
+}
+
+/// The content of a path at the end of a run
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PathState {
+    /// A fingerprint of the size and modification times of the files
+    pub stamp: String,
+    /// The SHA256 hash of the content of the files
+    pub hash: String,
 }
 
 /// Return the cache file for the current directory
@@ -70,59 +91,136 @@ pub fn write_cache(content: Cache) -> Result<()> {
     Ok(())
 }
 
-/// Check the name of the last profile run in this project and return
-/// True if it's the same as the current one, False otherwise.
-/// Returns None if there is no cache.
+/// Compute a cheap fingerprint of a file or directory, from the size and
+/// modification time of the files in it.
 ///
-/// Also updates the cache to the new profile
-#[allow(clippy::needless_update)]
-pub fn check_last_profile(current_profile: String) -> Option<bool> {
-    let last_profile = get_cache();
-
-    let result = last_profile.clone().is_some_and(|x| {
-        x.last_executed_profile
-            .is_some_and(|x| x == current_profile)
-    });
+/// If the fingerprint did not change, we assume that the content did not
+/// change either, and we skip hashing it again.
+fn stamp_path(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    for (relative, file) in files_in(path) {
+        let metadata = fs::metadata(&file)?;
+        let mtime = FileTime::from_last_modification_time(&metadata);
+        hasher.update(format!(
+            "{}\0{}\0{}.{}\n",
+            relative.to_string_lossy(),
+            metadata.len(),
+            mtime.unix_seconds(),
+            mtime.nanoseconds()
+        ));
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
 
-    let new_cache = Cache {
-        last_executed_profile: Some(current_profile),
-        // This line is where clippy complains: there is no need to do this
-        // as Cache has only the `last_executed_profile` field.
-        // But - just for now. It's added for future compatibility
-        ..last_profile.clone().unwrap_or_default()
-    };
-    match write_cache(new_cache) {
-        Ok(_) => {}
-        Err(_) => {
-            log::error!("New cache was generated but could not be written. Silently continuing.")
-        }
-    };
+/// Compute the SHA256 hash of the content of a file or directory
+///
+/// The hash of a directory depends on the relative paths and the content of
+/// all the files in it.
+fn hash_path(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    for (relative, file) in files_in(path) {
+        let mut file_hasher = Sha256::new();
+        io::copy(&mut File::open(&file)?, &mut file_hasher)?;
+        hasher.update(format!(
+            "{}\0{:x}\n",
+            relative.to_string_lossy(),
+            file_hasher.finalize()
+        ));
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
 
-    if last_profile.is_none() {
-        None
-    } else {
-        Some(result)
+/// List the files in a path, with their path relative to it, sorted
+///
+/// If the path is a file, this is just the file itself.
+fn files_in(path: &Path) -> Vec<(PathBuf, PathBuf)> {
+    if path.is_file() {
+        return vec![(PathBuf::new(), path.to_path_buf())];
     }
+    WalkDir::new(path)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|x| x.ok())
+        .filter(|x| x.path().is_file())
+        .map(|x| {
+            let relative = x.path().strip_prefix(path).unwrap().to_path_buf();
+            (relative, x.into_path())
+        })
+        .collect()
 }
 
-/// Delete from the cache the last profile used.
+/// Bump the modification time of the profiled paths whose content changed
+/// since the last run.
+///
+/// `served` are `(location, file)` pairs: the `file` is what the workflow
+/// will find at `location` during this run (e.g. the target of a profile),
+/// or None if the location will be empty. Locations profiled in previous
+/// runs that are not in `served` will serve their own content.
+///
+/// Only files whose content differs from what was served at the same
+/// location in the last run are touched, so switching profiles back and
+/// forth only makes `make` re-run what actually changed.
 ///
-/// This currently just deletes the cache.
-#[allow(clippy::needless_update)]
-pub fn delete_last_profile() -> Result<()> {
-    let cache = get_cache();
+/// Returns the new states of the locations, to be saved with
+/// `save_profiled_paths` once the profile is in place.
+pub fn touch_changed_paths(
+    served: &[(PathBuf, Option<PathBuf>)],
+) -> Result<HashMap<PathBuf, Option<PathState>>> {
+    let old_states = get_cache().unwrap_or_default().profiled_paths;
 
+    let mut served: HashMap<PathBuf, Option<PathBuf>> = served.iter().cloned().collect();
+    for location in old_states.keys() {
+        if !served.contains_key(location) {
+            let file = location.exists().then(|| location.clone());
+            served.insert(location.clone(), file);
+        }
+    }
+
+    let mut new_states = HashMap::with_capacity(served.len());
+    for (location, file) in served {
+        let file = match file.filter(|x| x.exists()) {
+            Some(x) => x,
+            None => {
+                new_states.insert(location, None);
+                continue;
+            }
+        };
+
+        let old_state = old_states.get(&location).cloned().flatten();
+        let stamp = stamp_path(&file)?;
+        let hash = match &old_state {
+            // The file was not touched since the last run
+            Some(state) if state.stamp == stamp => state.hash.clone(),
+            _ => hash_path(&file)?,
+        };
+
+        let state = if old_state.is_some_and(|x| x.hash == hash) {
+            PathState { stamp, hash }
+        } else {
+            log::debug!("Content at {:?} changed. Touching {:?}", location, file);
+            update_timestamps(&file)?;
+            PathState {
+                stamp: stamp_path(&file)?,
+                hash,
+            }
+        };
+        new_states.insert(location, Some(state));
+    }
+
+    Ok(new_states)
+}
+
+/// Save the states of the profiled paths, and the profile used, to the cache
+pub fn save_profiled_paths(
+    states: HashMap<PathBuf, Option<PathState>>,
+    profile: Option<String>,
+) -> Result<()> {
     let new_cache = Cache {
-        last_executed_profile: None,
-        // This line is where clippy complains: there is no need to do this
-        // as Cache has only the `last_executed_profile` field.
-        // But - just for now. It's added for future compatibility
-        ..cache.unwrap_or_default()
+        last_executed_profile: profile,
+        profiled_paths: states,
     };
 
-    write_cache(new_cache)?;
-
-    Ok(())
+    write_cache(new_cache)
 }
 
 /// Calculate the hash of some hashable object
@@ -131,3 +229,28 @@ fn calc_hash<T: Hash>(t: &T) -> u64 {
     t.hash(&mut s);
     s.finish()
 }
+
+#[test]
+fn test_hash_path() {
+    let root = tempfile::tempdir().unwrap();
+    let a = root.path().join("a.txt");
+    let b = root.path().join("b.txt");
+    fs::write(&a, "same").unwrap();
+    fs::write(&b, "same").unwrap();
+    filetime::set_file_mtime(&b, FileTime::from_unix_time(1000, 0)).unwrap();
+
+    // The hash only depends on the content, the stamp also on the mtime
+    assert_eq!(hash_path(&a).unwrap(), hash_path(&b).unwrap());
+    assert_ne!(stamp_path(&a).unwrap(), stamp_path(&b).unwrap());
+
+    fs::write(&b, "different").unwrap();
+    assert_ne!(hash_path(&a).unwrap(), hash_path(&b).unwrap());
+
+    // Directories are hashed by their content too
+    let dir = root.path().join("dir");
+    fs::create_dir(&dir).unwrap();
+    fs::write(dir.join("a.txt"), "same").unwrap();
+    let before = hash_path(&dir).unwrap();
+    fs::write(dir.join("a.txt"), "changed").unwrap();
+    assert_ne!(before, hash_path(&dir).unwrap());
+}
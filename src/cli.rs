@@ -4,7 +4,8 @@ use std::ffi::OsString;
 use anyhow::Result;
 
 use crate::commands::{
-    DataCommand, IgnoreCommand, NewCommand, PackageCommand, ReplayCommand, RunCommand,
+    CheckCommand, ConfigCommand, DataCommand, IgnoreCommand, NewCommand, PackageCommand,
+    ProfileCommand, RecoverCommand, ReplayCommand, RunCommand,
 };
 
 /// This string is displayed when the help message is invoked.
@@ -46,6 +47,10 @@ enum Command {
     Replay(ReplayCommand),
     Package(PackageCommand),
     Ignore(IgnoreCommand),
+    Check(CheckCommand),
+    Config(ConfigCommand),
+    Recover(RecoverCommand),
+    Profile(ProfileCommand),
 }
 
 impl Executable for Command {
@@ -57,6 +62,10 @@ impl Executable for Command {
             Self::Replay(x) => x.execute(),
             Self::Package(x) => x.execute(),
             Self::Ignore(x) => x.execute(),
+            Self::Check(x) => x.execute(),
+            Self::Config(x) => x.execute(),
+            Self::Recover(x) => x.execute(),
+            Self::Profile(x) => x.execute(),
         }
     }
 }
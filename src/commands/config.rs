@@ -0,0 +1,347 @@
+use std::env::{current_dir, set_current_dir};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cli::Executable;
+use crate::options::{
+    find_and_load_config_layers, find_kerblam_toml, migrate, set_dotted, validate_kerblam_toml,
+    ConfigLayers, KerblamTomlOptions, Schema, KERBLAM_TOML_SCHEMA, LOCAL_CONFIG_NAME, SETTINGS,
+};
+use crate::utils::{ask_for, YesNo};
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Args, Subcommand};
+use similar::TextDiff;
+use toml_edit::{DocumentMut, Key};
+
+/// Inspect and edit the configuration of the current project
+///
+/// The configuration of a project is read from the kerblam.toml file,
+/// with per-machine overrides from a kerblam.local.toml file next to it
+/// and from any file listed in the KERBLAM_CONFIG environment variable.
+///
+/// If no subcommand is specified, shows the effective configuration.
+///
+/// Examples:
+///     > Show the effective configuration, and where each value comes from
+///         kerblam config show
+///
+///     > Print the resolved input data directory
+///         kerblam config get data.paths.input
+///
+///     > Use podman to run containers, but only on this machine
+///         kerblam config set execution.backend podman --local
+///
+///     > Update an old kerblam.toml file to work with this version
+///         kerblam config migrate
+#[derive(Args, Debug, Clone)]
+#[command(verbatim_doc_comment)]
+pub struct ConfigCommand {
+    #[command(subcommand)]
+    subcommand: Option<ConfigSubcommands>,
+}
+
+impl Executable for ConfigCommand {
+    fn execute(self) -> Result<()> {
+        match self.subcommand {
+            Some(subcommand) => subcommand.execute(),
+            None => ShowCommand {}.execute(),
+        }
+    }
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum ConfigSubcommands {
+    Show(ShowCommand),
+    Get(GetCommand),
+    Set(SetCommand),
+    Migrate(MigrateCommand),
+}
+
+impl Executable for ConfigSubcommands {
+    fn execute(self) -> Result<()> {
+        match self {
+            Self::Show(x) => x.execute(),
+            Self::Get(x) => x.execute(),
+            Self::Set(x) => x.execute(),
+            Self::Migrate(x) => x.execute(),
+        }
+    }
+}
+
+/// Show the effective configuration of the project
+///
+/// Prints the resolved value of all settings, marking the ones that are
+/// left to their default, and which file every other value comes from.
+#[derive(Args, Debug, Clone)]
+pub struct ShowCommand {}
+
+impl Executable for ShowCommand {
+    fn execute(self) -> Result<()> {
+        let layers = find_and_load_config_layers()?;
+        let config = KerblamTomlOptions::try_from_layers(&layers)?;
+        println!("{}", describe_layers(&layers, &config, &current_dir()?));
+        Ok(())
+    }
+}
+
+/// Print the resolved value of a setting
+///
+/// Paths are printed relative to the project root, after expanding
+/// environment variables. If the setting is not configured, its default
+/// value is printed.
+#[derive(Args, Debug, Clone)]
+pub struct GetCommand {
+    /// The dotted key of the setting, e.g. 'data.paths.input'
+    key: String,
+}
+
+impl Executable for GetCommand {
+    fn execute(self) -> Result<()> {
+        let layers = find_and_load_config_layers()?;
+        let config = KerblamTomlOptions::try_from_layers(&layers)?;
+
+        if let Some(value) = config.setting(&self.key) {
+            println!("{}", value);
+            return Ok(());
+        }
+
+        match lookup_dotted(&layers.merged, &self.key) {
+            Some(toml::Value::String(value)) => println!("{}", value),
+            Some(value) => println!("{}", value),
+            None => {
+                if schema_lookup(&self.key).is_some() {
+                    bail!("`{}` is not set, and has no default.", self.key)
+                }
+                bail!("Unknown configuration key `{}`", self.key)
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Set the value of a setting in the kerblam.toml file
+///
+/// The file is edited in place, preserving its comments and formatting,
+/// and is validated before being written.
+/// String settings (like paths) are set verbatim; other values are parsed
+/// as TOML values, e.g. '{ "a.txt" = "b.txt" }'.
+#[derive(Args, Debug, Clone)]
+pub struct SetCommand {
+    /// The dotted key of the setting, e.g. 'data.paths.input'
+    key: String,
+    /// The new value of the setting
+    value: String,
+    /// Edit the kerblam.local.toml file instead of kerblam.toml
+    #[arg(long)]
+    local: bool,
+}
+
+impl Executable for SetCommand {
+    fn execute(self) -> Result<()> {
+        let toml_file = find_kerblam_toml().ok_or(anyhow!(
+            "Not a kerblam! project (or any of the parent directories): no kerblam.toml found."
+        ))?;
+        let target = if self.local {
+            toml_file.parent().unwrap().join(LOCAL_CONFIG_NAME)
+        } else {
+            toml_file
+        };
+
+        let content = if target.exists() {
+            fs::read_to_string(&target).with_context(|| format!("Cannot read {:?}", target))?
+        } else {
+            String::new()
+        };
+        let mut document: DocumentMut = content
+            .parse()
+            .with_context(|| format!("Cannot parse {:?}", target))?;
+
+        let value = match schema_lookup(&self.key) {
+            Some(schema) if !schema.is_string() => self
+                .value
+                .parse::<toml_edit::Value>()
+                .map_err(|e| anyhow!("{:?} is not a valid TOML value: {}", self.value, e))?,
+            // Unknown keys are caught by the validation below
+            _ => toml_edit::Value::from(self.value.as_str()),
+        };
+        set_dotted(&mut document, &self.key, value)?;
+
+        let new_content = document.to_string();
+        let issues = validate_kerblam_toml(&new_content, &target);
+        if !issues.is_empty() {
+            bail!(
+                "Refusing to set `{}`, as the result would be invalid:\n{}",
+                self.key,
+                issues
+                    .iter()
+                    .map(|x| format!("\t- {}", x))
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            )
+        }
+
+        fs::write(&target, new_content).with_context(|| format!("Cannot write {:?}", target))?;
+        eprintln!("✅ Set `{}` in {:?}", self.key, target);
+
+        Ok(())
+    }
+}
+
+/// Update the kerblam.toml file to the schema of this version of Kerblam!
+///
+/// Applies the changes needed to keep an old kerblam.toml file working as
+/// it did with the version of Kerblam! it was written for, and updates its
+/// `meta.version`. Shows the changes, and asks for confirmation, before
+/// writing anything.
+#[derive(Args, Debug, Clone)]
+pub struct MigrateCommand {
+    /// Do not ask for confirmation before writing the changes
+    #[arg(long, short)]
+    yes: bool,
+    /// Only show the changes, without writing them
+    #[arg(long)]
+    dry_run: bool,
+}
+
+impl Executable for MigrateCommand {
+    fn execute(self) -> Result<()> {
+        let toml_file = find_kerblam_toml().ok_or(anyhow!(
+            "Not a kerblam! project (or any of the parent directories): no kerblam.toml found."
+        ))?;
+        // Migrations can look at the project, so they need to run in its root
+        set_current_dir(toml_file.parent().unwrap())?;
+
+        let content = fs::read_to_string(&toml_file)
+            .with_context(|| format!("Cannot read {:?}", toml_file))?;
+        let mut document: DocumentMut = content
+            .parse()
+            .with_context(|| format!("Cannot parse {:?}", toml_file))?;
+
+        let applied = migrate(&mut document)?;
+        let new_content = document.to_string();
+
+        if new_content == content {
+            eprintln!("✅ The kerblam.toml file is already up to date.");
+            return Ok(());
+        }
+
+        if applied.is_empty() {
+            eprintln!("No schema changes are needed: only `meta.version` will be updated.");
+        } else {
+            eprintln!("The following changes will be made:");
+            for migration in applied {
+                eprintln!(
+                    "    - (since {}) {}",
+                    migration.version, migration.description
+                );
+            }
+        }
+        println!(
+            "{}",
+            TextDiff::from_lines(&content, &new_content)
+                .unified_diff()
+                .header("kerblam.toml", "kerblam.toml (migrated)")
+        );
+
+        if self.dry_run {
+            return Ok(());
+        }
+        if !self.yes && !bool::from(ask_for::<YesNo>("Write these changes?")) {
+            eprintln!("Nothing was changed.");
+            return Ok(());
+        }
+
+        fs::write(&toml_file, new_content)
+            .with_context(|| format!("Cannot write {:?}", toml_file))?;
+        eprintln!("✅ Migrated {:?}", toml_file);
+
+        Ok(())
+    }
+}
+
+/// Find the schema of a dotted key, if it is a valid key of kerblam.toml
+fn schema_lookup(dotted: &str) -> Option<&'static Schema> {
+    let keys = Key::parse(dotted).ok()?;
+    let keys: Vec<&str> = keys.iter().map(|x| x.get()).collect();
+    KERBLAM_TOML_SCHEMA.lookup(&keys)
+}
+
+/// Make a path relative to the project root, if possible, for display
+fn pretty_path(path: &Path, root: &Path) -> PathBuf {
+    path.strip_prefix(root).unwrap_or(path).to_path_buf()
+}
+
+/// Format lines of `key = value` with a trailing comment, aligning the comments
+fn aligned(rows: Vec<(String, String)>) -> Vec<String> {
+    let width = rows.iter().map(|(x, _)| x.len()).max().unwrap_or(0);
+    rows.into_iter()
+        .map(|(line, comment)| format!("    {:width$}  # {}", line, comment))
+        .collect()
+}
+
+/// Describe the effective configuration, with the origin of each value
+fn describe_layers(layers: &ConfigLayers, config: &KerblamTomlOptions, root: &Path) -> String {
+    let mut lines: Vec<String> = vec!["📄 Configuration files (lowest precedence first):".into()];
+    for (i, file) in layers.files.iter().enumerate() {
+        lines.push(format!(
+            "    {}. {}",
+            i + 1,
+            pretty_path(file, root).to_string_lossy()
+        ));
+    }
+    lines.push(String::new());
+
+    lines.push("⚙️ Settings:".into());
+    let settings: Vec<(String, String)> = SETTINGS
+        .iter()
+        .filter_map(|(key, _)| {
+            let value = config.setting(key)?;
+            let origin = match layers.origins.get(*key) {
+                Some(file) => format!("from {}", pretty_path(file, root).to_string_lossy()),
+                None => "default".to_string(),
+            };
+            Some((format!("{} = {}", key, toml::Value::String(value)), origin))
+        })
+        .collect();
+    lines.extend(aligned(settings));
+
+    let others: Vec<(String, String)> = layers
+        .origins
+        .iter()
+        .filter(|(key, _)| !SETTINGS.iter().any(|(setting, _)| setting == key))
+        .map(|(key, file)| {
+            let value = lookup_dotted(&layers.merged, key)
+                .map(|x| x.to_string())
+                .unwrap_or_default();
+            (
+                format!("{} = {}", key, value),
+                format!("from {}", pretty_path(file, root).to_string_lossy()),
+            )
+        })
+        .collect();
+    if !others.is_empty() {
+        lines.push(String::new());
+        lines.push("🗂️ Profiles and remote files:".into());
+        lines.extend(aligned(others));
+    }
+
+    lines.join("\n")
+}
+
+/// Find a value in a TOML table given its dotted key
+///
+/// The dotted key is parsed as a TOML key, so quoted pieces (e.g.
+/// `data.profiles.test."input.txt"`) are supported.
+pub fn lookup_dotted<'a>(table: &'a toml::Table, dotted: &str) -> Option<&'a toml::Value> {
+    let keys = Key::parse(dotted).ok()?;
+    let (first, rest) = keys.split_first()?;
+
+    let mut current = table.get(first.get())?;
+    for key in rest {
+        current = current.as_table()?.get(key.get())?;
+    }
+
+    Some(current)
+}
@@ -1,14 +1,18 @@
+mod config;
 mod data;
 mod new;
 mod other;
 mod package;
+mod profile;
 mod replay;
 mod run;
 
 // Re-export only the functions that execute commands
+pub use config::ConfigCommand;
 pub use data::DataCommand;
 pub use new::NewCommand;
-pub use other::IgnoreCommand;
+pub use other::{CheckCommand, IgnoreCommand, RecoverCommand};
 pub use package::PackageCommand;
+pub use profile::ProfileCommand;
 pub use replay::ReplayCommand;
 pub use run::RunCommand;
@@ -1,15 +1,18 @@
 use std::{
-    env::current_dir,
-    fs::{copy, read, File},
+    env::{current_dir, set_current_dir},
+    fs::{copy, read, read_to_string, File},
     io::Write,
     path::{Path, PathBuf},
 };
 
 use crate::cli::Executable;
-use crate::options::find_and_parse_kerblam_toml;
+use crate::journal::Journal;
+use crate::options::{
+    config_files, find_and_parse_kerblam_toml, find_kerblam_toml, validate_kerblam_toml,
+};
 use crate::utils::fetch_gitignore;
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use clap::Args;
 
 /// Add paths and whole languages to a .gitignore file
@@ -181,3 +184,102 @@ pub fn ignore(target: impl AsRef<Path>, pattern: &str, compress: bool) -> Result
 
     Ok(())
 }
+
+/// Check the kerblam.toml file for errors
+///
+/// Validates the kerblam.toml file of the current project (and any local
+/// override, like kerblam.local.toml), reporting unknown keys (e.g. typos),
+/// values of the wrong type and invalid remote URLs, with the line and
+/// column where they are found.
+///
+/// Nothing else is done: no data is touched and no workflow is run.
+///
+/// Examples:
+///     > Check the current project's configuration
+///         kerblam check
+#[derive(Args, Debug, Clone)]
+#[command(verbatim_doc_comment)]
+pub struct CheckCommand {}
+
+impl Executable for CheckCommand {
+    fn execute(self) -> Result<()> {
+        let toml_file = match find_kerblam_toml() {
+            Some(path) => path,
+            None => bail!(
+                "Not a kerblam! project (or any of the parent directories): no kerblam.toml found."
+            ),
+        };
+
+        let mut failed = 0;
+        for file in config_files(toml_file)? {
+            if check_kerblam_toml(file).is_err() {
+                failed += 1;
+            }
+        }
+
+        if failed > 0 {
+            bail!("{} configuration file(s) are not valid.", failed)
+        }
+
+        Ok(())
+    }
+}
+
+/// Restore the input data after an interrupted run
+///
+/// While a data profile is in use, Kerblam! keeps a journal of the changes
+/// it makes to the input data directory. If Kerblam! is killed before it
+/// can put things back (e.g. by SIGKILL, or if the machine reboots), this
+/// command undoes those changes, newest first.
+///
+/// `kerblam run` also offers to do this before running a workflow.
+///
+/// Examples:
+///     > Restore the input data of the current project
+///         kerblam recover
+#[derive(Args, Debug, Clone)]
+#[command(verbatim_doc_comment)]
+pub struct RecoverCommand {}
+
+impl Executable for RecoverCommand {
+    fn execute(self) -> Result<()> {
+        // The configuration might be broken: we only need the project root
+        let toml_file = match find_kerblam_toml() {
+            Some(path) => path,
+            None => bail!(
+                "Not a kerblam! project (or any of the parent directories): no kerblam.toml found."
+            ),
+        };
+        set_current_dir(toml_file.parent().unwrap())?;
+
+        let journal = Journal::current();
+        if journal.is_empty() {
+            eprintln!("✅ Nothing to recover.");
+            return Ok(());
+        }
+
+        eprintln!("Undoing the changes of the interrupted run...");
+        journal.replay()?;
+        eprintln!("✅ Restored the input data.");
+
+        Ok(())
+    }
+}
+
+/// Validate a kerblam.toml file, printing every issue found in it.
+pub fn check_kerblam_toml(toml_file: impl AsRef<Path>) -> Result<()> {
+    let toml_file = toml_file.as_ref();
+    let content = read_to_string(toml_file)?;
+    let issues = validate_kerblam_toml(&content, toml_file);
+
+    if issues.is_empty() {
+        println!("✅ {:?} is valid!", toml_file);
+        return Ok(());
+    }
+
+    for issue in &issues {
+        eprintln!("❌ {}", issue);
+    }
+
+    bail!("Found {} issue(s) in {:?}", issues.len(), toml_file)
+}
@@ -10,8 +10,8 @@ use std::{
 use crate::cli::Executable;
 use crate::execution::Executor;
 use crate::options::find_and_parse_kerblam_toml;
-use crate::options::KerblamTomlOptions;
 use crate::options::Pipe;
+use crate::options::{KerblamTomlOptions, DEFAULT_WORKDIR};
 use crate::utils::{find_files, find_pipe_by_name, gzip_file, tar_files};
 
 use anyhow::{bail, Result};
@@ -52,6 +52,7 @@ impl Executable for PackageCommand {
         let config = find_and_parse_kerblam_toml()?;
         let default_pipe_name = format!("{}_exec", &self.pipe.clone().unwrap_or("x".to_string()));
         let pipe = find_pipe_by_name(&config, self.pipe)?;
+        pipe.check()?;
         package_pipe(
             config,
             pipe,
@@ -112,6 +113,18 @@ pub fn package_pipe(
     include_signature: bool,
 ) -> Result<()> {
     let pipe_name = pipe.name();
+    if pipe.metadata.profile_required {
+        bail!(
+            "Cannot package pipe {:?}: it must be run with a data profile.",
+            pipe_name
+        )
+    }
+    if !config.execution.backend.is_docker_like() {
+        bail!(
+            "Cannot package pipe {:?}: packaging needs the docker or podman backend. Packages can then be replayed with Apptainer.",
+            pipe_name
+        )
+    }
     log::debug!("Packaging pipe {pipe_name} as {package_name}...");
     let here = current_dir()?;
 
@@ -146,7 +159,8 @@ pub fn package_pipe(
     set_current_dir(temp_build_dir.path())?;
 
     log::debug!("Building initial context...");
-    let executor: Executor = pipe.into_executor(&here)?;
+    let uses_image = pipe.metadata.image.is_some();
+    let executor: Executor = pipe.into_executor(&here, &config.execution.interpreters)?;
     let myself = current_exe()?;
 
     if !executor.has_env() {
@@ -155,8 +169,15 @@ pub fn package_pipe(
             pipe_name
         )
     };
+    if executor.strategy().manages_containers() {
+        bail!(
+            "Cannot package pipe {:?}: its workflow manager runs its own containers.",
+            pipe_name
+        )
+    }
     let backend: String = config.execution.backend.clone().into();
-    let base_container = executor.build_env(&backend, false)?;
+    let base_container =
+        executor.build_env(&config.execution.backend, false, config.execution.offline)?;
     log::debug!("Base container name: {base_container:?}");
 
     // We now have the empty container. We can add our own layers.
@@ -166,21 +187,29 @@ pub fn package_pipe(
     let workdir = config.execution.workdir.clone();
     let workdir = match workdir {
         Some(p) => format!("{}", p.to_string_lossy()),
-        None => "/".into(),
+        None => DEFAULT_WORKDIR.into(),
     };
     // Copy the kerblam! executable here, so we can include it in the context.
     log::debug!("Copying kerblam! executable to context...");
     let kerblam_path = temp_build_dir.path().join("kerblam");
     copy(myself, kerblam_path)?;
 
-    // Create the execution file for replay
-    let execution = format!("set -euo pipefail\n./kerblam data fetch\n./kerblam run {pipe_name}");
+    // Create the execution file for replay. We are already in the container,
+    // so the pipe must not try to start one.
+    let execution =
+        format!("set -euo pipefail\n./kerblam data fetch\n./kerblam run {pipe_name} --local");
     let execution_file_path = temp_build_dir.path().join("replay.sh");
     let mut new_execution_file = File::create(&execution_file_path)?;
     new_execution_file.write_all(execution.as_bytes())?;
 
+    // Existing images do not have the project in them, so we add it
+    let copied = if uses_image {
+        "."
+    } else {
+        "./kerblam ./replay.sh"
+    };
     let content = format!(
-        "FROM {base_container}\nWORKDIR {workdir}\nCOPY ./kerblam ./replay.sh .\nENTRYPOINT [\"/bin/bash\", \"./replay.sh\"]"
+        "FROM {base_container}\nWORKDIR {workdir}\nCOPY {copied} .\nENTRYPOINT [\"/bin/bash\", \"./replay.sh\"]"
     );
     log::debug!("Execution string: {content}");
     let new_container_file_path = temp_build_dir.path().join("Containerfile");
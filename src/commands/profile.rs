@@ -0,0 +1,285 @@
+use std::collections::HashSet;
+use std::env::current_dir;
+use std::path::{Path, PathBuf};
+
+use crate::cli::Executable;
+use crate::options::{
+    describe_profile, expand_profile_paths, find_and_parse_kerblam_toml, missing_profile_paths,
+    InferenceRule, InferredPair, KerblamTomlOptions, ProfileMode,
+};
+
+use anyhow::{bail, Result};
+use clap::{Args, Subcommand};
+
+/// List, inspect and validate the data profiles of the current project
+///
+/// If no subcommand is specified, lists the available profiles.
+///
+/// Examples:
+///     > List all profiles, including the automatic 'test' profile
+///         kerblam profile
+///
+///     > Show the files that the 'test' profile swaps, and if they exist
+///         kerblam profile show test
+///
+///     > Check that all profiles can be used, without moving any file
+///         kerblam profile check
+///
+///     > Show which files the automatic 'test' profile would swap
+///         kerblam profile inferred
+#[derive(Args, Debug, Clone)]
+#[command(verbatim_doc_comment)]
+pub struct ProfileCommand {
+    #[command(subcommand)]
+    subcommand: Option<ProfileSubcommands>,
+}
+
+impl Executable for ProfileCommand {
+    fn execute(self) -> Result<()> {
+        match self.subcommand {
+            Some(subcommand) => subcommand.execute(),
+            None => ListCommand {}.execute(),
+        }
+    }
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum ProfileSubcommands {
+    List(ListCommand),
+    Show(ShowCommand),
+    Check(CheckCommand),
+    Inferred(InferredCommand),
+}
+
+impl Executable for ProfileSubcommands {
+    fn execute(self) -> Result<()> {
+        match self {
+            Self::List(x) => x.execute(),
+            Self::Show(x) => x.execute(),
+            Self::Check(x) => x.execute(),
+            Self::Inferred(x) => x.execute(),
+        }
+    }
+}
+
+/// Return the names of all profiles, sorted
+fn profile_names(config: &KerblamTomlOptions) -> Vec<String> {
+    let mut names: Vec<String> = config.profiles().into_keys().collect();
+    names.sort_unstable();
+    names
+}
+
+/// Is this profile the automatically inferred one?
+fn is_inferred(config: &KerblamTomlOptions, name: &str) -> bool {
+    let explicit = config.data.as_ref().and_then(|x| x.profiles.as_ref());
+    name == "test" && !explicit.is_some_and(|x| x.contains_key("test"))
+}
+
+/// List the available data profiles
+///
+/// Shows the profile that each one extends, its mode and how many paths
+/// it swaps.
+#[derive(Args, Debug, Clone)]
+pub struct ListCommand {}
+
+impl Executable for ListCommand {
+    fn execute(self) -> Result<()> {
+        let config = find_and_parse_kerblam_toml()?;
+        let profiles = config.profiles();
+
+        let mut lines: Vec<String> = vec!["📂 Available profiles:".into()];
+        for name in profile_names(&config) {
+            let mut details: Vec<String> = vec![];
+            if is_inferred(&config, &name) {
+                details.push("inferred".into());
+            }
+            if let Some(parent) = &profiles[&name].extends {
+                details.push(format!("extends {}", parent));
+            }
+            match config.resolved_profile(&name) {
+                Ok(paths) if paths.len() == 1 => details.push("1 path".into()),
+                Ok(paths) => details.push(format!("{} paths", paths.len())),
+                Err(e) => details.push(format!("❌ {}", e)),
+            }
+            if let Ok(ProfileMode::Symlink) = config.profile_mode(&name) {
+                details.push("symlink mode".into());
+            }
+            lines.push(format!("    - {} ({})", name, details.join(", ")));
+        }
+        if lines.len() == 1 {
+            lines.push("    No profiles found.".into());
+        }
+
+        println!("{}", lines.join("\n"));
+        Ok(())
+    }
+}
+
+/// Show the resolved paths of a profile
+///
+/// Shows the paths of the profile after following the profiles that it
+/// extends, and every file that it swaps after expanding glob patterns,
+/// marking the ones that do not exist.
+#[derive(Args, Debug, Clone)]
+pub struct ShowCommand {
+    /// The name of the profile to show
+    name: String,
+}
+
+impl Executable for ShowCommand {
+    fn execute(self) -> Result<()> {
+        let config = find_and_parse_kerblam_toml()?;
+        println!("{}", describe_profile(&config, &self.name)?);
+
+        let swaps = expand_profile_paths(&config, &self.name)?;
+        let missing: HashSet<PathBuf> = missing_profile_paths(&config, &self.name)?
+            .into_iter()
+            .collect();
+        let status = |path: &PathBuf| if missing.contains(path) { "❌" } else { "✅" };
+
+        let mut lines: Vec<String> = vec![String::new(), "📄 Swapped files:".into()];
+        let mut swaps: Vec<&(PathBuf, Option<PathBuf>)> = swaps.iter().collect();
+        swaps.sort_unstable();
+        for (original, target) in swaps {
+            let line = match target {
+                Some(target) => format!(
+                    "    {} {} <- {} {}",
+                    status(original),
+                    original.to_string_lossy(),
+                    status(target),
+                    target.to_string_lossy()
+                ),
+                None => format!(
+                    "    {} {} (hidden)",
+                    status(original),
+                    original.to_string_lossy()
+                ),
+            };
+            lines.push(line);
+        }
+        println!("{}", lines.join("\n"));
+
+        if !missing.is_empty() {
+            eprintln!("\n⚠️  Some files do not exist: the profile cannot be used.");
+        }
+
+        Ok(())
+    }
+}
+
+/// Check that all profiles can be used
+///
+/// Resolves every profile, and checks that all the files that it swaps
+/// exist, exactly like `kerblam run` does, but without moving any file.
+#[derive(Args, Debug, Clone)]
+pub struct CheckCommand {}
+
+impl Executable for CheckCommand {
+    fn execute(self) -> Result<()> {
+        let config = find_and_parse_kerblam_toml()?;
+
+        let mut failed = 0;
+        for name in profile_names(&config) {
+            match check_profile(&config, &name) {
+                Ok(_) => eprintln!("✅ {}", name),
+                Err(e) => {
+                    eprintln!("❌ {}: {}", name, e);
+                    failed += 1;
+                }
+            }
+        }
+
+        if failed > 0 {
+            bail!("{} profile(s) are not valid.", failed)
+        }
+
+        Ok(())
+    }
+}
+
+/// Make all the checks that are made before using a profile
+fn check_profile(config: &KerblamTomlOptions, name: &str) -> Result<()> {
+    config.profile_mode(name)?;
+    config.profile_redirects(name)?;
+    config.profile_env(name)?;
+
+    let missing = missing_profile_paths(config, name)?;
+    if !missing.is_empty() {
+        bail!(
+            "Failed to find some profiles files:\n{}",
+            missing
+                .iter()
+                .map(|x| format!("\t- {}", x.to_string_lossy()))
+                .collect::<Vec<String>>()
+                .join("\n")
+        )
+    }
+
+    Ok(())
+}
+
+/// Show the automatically inferred 'test' profile
+///
+/// Lists the inference rules (from `[data.inference]`, or the default
+/// `test_` prefix rule), and every pair of files that they find, with the
+/// rule that found it.
+#[derive(Args, Debug, Clone)]
+pub struct InferredCommand {}
+
+impl Executable for InferredCommand {
+    fn execute(self) -> Result<()> {
+        let config = find_and_parse_kerblam_toml()?;
+        let rules = config.inference_rules();
+        let pairs = config.inferred_test_pairs();
+        let has_explicit_test = !is_inferred(&config, "test");
+
+        println!(
+            "{}",
+            describe_inference(&rules, &pairs, &config.input_data_dir())
+        );
+        if has_explicit_test {
+            eprintln!(
+                "\n⚠️  There is a 'test' profile in the kerblam.toml file, so this one is not used."
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Describe the inference rules and the pairs they found, for the user
+fn describe_inference(rules: &[InferenceRule], pairs: &[InferredPair], input_dir: &Path) -> String {
+    let mut lines: Vec<String> = vec!["🔍 Inference rules:".into()];
+    for (i, rule) in rules.iter().enumerate() {
+        lines.push(format!("    {}. {}", i + 1, rule));
+    }
+    if rules.is_empty() {
+        lines.push("    (no rules: inference is disabled)".into());
+    }
+    lines.push(String::new());
+
+    // Targets might be outside the input dir, e.g. with directory rules
+    let root = current_dir().unwrap();
+    let pretty = |path: &Path| -> String {
+        path.strip_prefix(input_dir)
+            .or(path.strip_prefix(&root))
+            .unwrap_or(path)
+            .to_string_lossy()
+            .to_string()
+    };
+
+    lines.push("📂 Inferred 'test' profile:".into());
+    for pair in pairs {
+        lines.push(format!(
+            "    {} <- {}  (rule {})",
+            pretty(&pair.original),
+            pretty(&pair.target),
+            pair.rule + 1
+        ));
+    }
+    if pairs.is_empty() {
+        lines.push("    (no files found)".into());
+    }
+
+    lines.join("\n")
+}
@@ -8,8 +8,11 @@ use clap::Args;
 use tempfile::TempDir;
 
 use crate::cli::Executable;
-use crate::execution::{generate_bind_mount_strings, run_protected_command, CommandResult};
-use crate::options::{ContainerBackend, KerblamTomlOptions};
+use crate::execution::{
+    generate_bind_mount_strings, image_reference, run_protected_command, CommandResult,
+    MountOverrides,
+};
+use crate::options::{ContainerBackend, KerblamTomlOptions, DEFAULT_WORKDIR};
 use crate::utils::gunzip_file;
 
 /// Replay a pipeline previously packaged with `package`
@@ -22,9 +25,15 @@ use crate::utils::gunzip_file;
 /// in their correct positions and starts the replay workflow on top
 /// with the correct mountpoints.
 ///
-/// Example:
+/// With Apptainer, the container is pulled from its registry, unless the
+/// tag is the path to an image file.
+///
+/// Examples:
 ///     > Replay the 'test.kerblam.tar' replay package
 ///         kerblam replay test.kerblam.tar
+///
+///     > Replay it with Apptainer, from an image file
+///         kerblam replay test.kerblam.tar --backend apptainer --tag test.sif
 #[derive(Args, Debug, Clone)]
 #[command(verbatim_doc_comment)]
 pub struct ReplayCommand {
@@ -39,7 +48,7 @@ pub struct ReplayCommand {
     /// instructions of the .kerblam file.
     #[arg(long, short)]
     tag: Option<String>,
-    /// The backend to use, either 'docker' or 'podman'
+    /// The backend to use: 'docker', 'podman', 'apptainer' or 'singularity'
     #[arg(long, short)]
     #[clap(default_value = "docker")]
     backend: ContainerBackend,
@@ -103,9 +112,8 @@ pub fn replay(
         }
     };
 
-    let package_config: KerblamTomlOptions = toml::from_str(&read_to_string(
-        decompression_dir.path().join("kerblam.toml"),
-    )?)?;
+    let package_config =
+        KerblamTomlOptions::try_from_file(decompression_dir.path().join("kerblam.toml"))?;
 
     let data_archive = decompression_dir.path().join("data.tar.gz");
     if !data_archive.exists() {
@@ -136,22 +144,47 @@ pub fn replay(
 
     log::debug!("Calling container backend for execution...");
 
-    let backend: String = backend.into();
-    let bind_mounts = generate_bind_mount_strings(&package_config);
+    let bind_mounts = generate_bind_mount_strings(&package_config, &MountOverrides::default());
+    let mount_flag = if backend.is_docker_like() {
+        "-v"
+    } else {
+        "--bind"
+    };
     let mut mounts: Vec<String> = vec![];
     for item in bind_mounts {
-        mounts.push("-v".to_string());
+        mounts.push(mount_flag.to_string());
         mounts.push(item.to_string());
     }
 
+    let run_args: Vec<String> = if backend.is_docker_like() {
+        vec!["run".into(), "-it".into(), "--rm".into()]
+    } else {
+        // Apptainer does not start in the workdir of the image by itself
+        let workdir = package_config
+            .execution
+            .workdir
+            .clone()
+            .unwrap_or(PathBuf::from(DEFAULT_WORKDIR));
+        vec![
+            "run".into(),
+            "--no-home".into(),
+            "--pwd".into(),
+            workdir.to_string_lossy().to_string(),
+        ]
+    };
+    let image = if backend.is_docker_like() {
+        tag_name
+    } else {
+        image_reference(&tag_name)
+    };
+
+    let backend: String = backend.into();
     let mut command = Command::new(backend);
     let builder = || {
         command
-            .arg("run")
-            .arg("-it")
-            .arg("--rm")
+            .args(run_args)
             .args(mounts)
-            .arg(tag_name)
+            .arg(image)
             .stdout(Stdio::inherit())
             .stdin(Stdio::inherit())
             .stderr(Stdio::inherit())
@@ -1,19 +1,27 @@
 use std::collections::HashMap;
 use std::env::current_dir;
+use std::fmt::Display;
+use std::io::{stdin, IsTerminal};
 use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
-use crate::cache::{check_last_profile, delete_last_profile, get_cache};
+use crate::cache::{save_profiled_paths, touch_changed_paths};
 use crate::cli::Executable;
-use crate::execution::{Executor, FileMover};
-use crate::options::extract_profile_paths;
+use crate::execution::{ExecutionStrategy, Executor, FileMover, Overlay, Redirect};
+use crate::journal::{Journal, JournalEntry};
 use crate::options::find_and_parse_kerblam_toml;
 use crate::options::KerblamTomlOptions;
 use crate::options::Pipe;
+use crate::options::PipeMetadata;
+use crate::options::{
+    dependency_order, describe_profile, expand_profile_paths, extract_profile_paths,
+    format_duration, resolve_params, ProfileMode,
+};
 use crate::utils::find_pipe_by_name;
 use crate::utils::print_md;
-use crate::utils::update_timestamps;
+use crate::utils::{ask_for, YesNo};
 
-use anyhow::{anyhow, bail, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use clap::Args;
 
 /// Start a workflow within a Kerblam! project
@@ -32,6 +40,15 @@ use clap::Args;
 ///
 ///     > Use the 'test' profile with a workflow
 ///         kerblam run process_csv --profile test
+///
+///     > Set the 'threads' parameter of a workflow
+///         kerblam run process_csv --set threads=8
+///
+///     > Run the workflows that 'report' depends on, then 'report' itself
+///         kerblam run report --with-deps
+///
+///     > Run all the steps of the 'full' pipeline set in kerblam.toml
+///         kerblam run full
 #[derive(Args, Debug, Clone)]
 #[command(verbatim_doc_comment)]
 pub struct RunCommand {
@@ -40,7 +57,7 @@ pub struct RunCommand {
     /// Name of a data profile to use during this execution
     #[arg(long)]
     profile: Option<String>,
-    /// Show the pipe description and exit
+    /// Show the pipe description (and the resolved profile, if any) and exit
     #[arg(long, short, action)]
     desc: bool,
     /// Do not run in container, even if a container is available
@@ -49,6 +66,15 @@ pub struct RunCommand {
     /// Do not use the containerization engine build cache if running in a container
     #[arg(long = "no-build-cache", action)]
     skip_build_cache: bool,
+    /// Never pull container images, failing if they are not available locally
+    #[arg(long, action)]
+    offline: bool,
+    /// Set a parameter of the workflow, as 'name=value'. Can be repeated.
+    #[arg(long = "set", value_name = "NAME=VALUE")]
+    params: Vec<String>,
+    /// Run the workflows that this workflow depends on first, in order
+    #[arg(long = "with-deps", action)]
+    with_deps: bool,
     /// Command line arguments to be passed to child process
     #[clap(last = true, allow_hyphen_values = true)]
     extra_args: Option<Vec<String>>,
@@ -56,175 +82,600 @@ pub struct RunCommand {
 
 impl Executable for RunCommand {
     fn execute(self) -> Result<()> {
-        let config = find_and_parse_kerblam_toml()?;
-        let pipe = find_pipe_by_name(&config, self.module_name)?;
+        let mut config = find_and_parse_kerblam_toml()?;
+        config.execution.offline |= self.offline;
+        if let Some(name) = self
+            .module_name
+            .clone()
+            .filter(|x| config.pipelines.contains_key(x))
+        {
+            return run_pipeline(config, &name, self);
+        }
+        let pipe = find_pipe_by_name(&config, self.module_name.clone())?;
         if self.desc {
             print_md(&pipe.long_description());
+            if let Some(profile) = &self.profile {
+                println!("\n{}", describe_profile(&config, profile)?);
+            }
             return Ok(());
         }
+        if self.with_deps {
+            return run_with_dependencies(config, pipe, self);
+        }
+        let pipe = if self.local { pipe.drop_env() } else { pipe };
         kerblam_run_project(
             config,
             pipe,
             &current_dir().unwrap(),
             self.profile,
-            self.local,
+            self.params,
             self.skip_build_cache,
             self.extra_args,
         )
     }
 }
 
+/// Split the `--set` parameters of a run between the pipes that it runs
+///
+/// Parameters like `pipe.name=value` go to that pipe, and the others to the
+/// `target` pipe. Fails if a parameter is for a pipe that is not run.
+fn split_params(
+    params: Vec<String>,
+    target: &str,
+    pipes: &[String],
+) -> Result<HashMap<String, Vec<String>>> {
+    let mut split: HashMap<String, Vec<String>> = HashMap::new();
+    for item in params {
+        let scoped = item
+            .split_once('=')
+            .and_then(|(name, value)| name.rsplit_once('.').map(|x| (x, value)));
+        match scoped {
+            Some(((pipe, name), value)) => {
+                if !pipes.iter().any(|x| x == pipe) {
+                    bail!(
+                        "The parameter {:?} is for the pipe '{}', but it is not run. The pipes that are run are: {}",
+                        item,
+                        pipe,
+                        pipes.join(", ")
+                    )
+                }
+                split
+                    .entry(pipe.to_string())
+                    .or_default()
+                    .push(format!("{}={}", name, value));
+            }
+            None => split.entry(target.to_string()).or_default().push(item),
+        }
+    }
+
+    Ok(split)
+}
+
+/// Run a pipe after all the pipes that it depends on, stopping at the first
+/// one that fails.
+///
+/// All pipes use the same profile and options, but the extra arguments only
+/// go to the pipe that was asked for. Parameters go to that pipe too,
+/// unless they are set for another one as `pipe.name=value`.
+fn run_with_dependencies(
+    mut config: KerblamTomlOptions,
+    pipe: Pipe,
+    options: RunCommand,
+) -> Result<()> {
+    let pipes = config.pipes()?;
+    let dependencies: HashMap<String, Vec<String>> = pipes
+        .iter()
+        .map(|x| (x.name(), x.metadata.depends_on.clone()))
+        .collect();
+    let order = dependency_order(&pipe.name(), &dependencies)?;
+    let mut params = split_params(options.params, &pipe.name(), &order)?;
+    // Do not run anything if a step cannot be run
+    for name in &order {
+        let step = pipes.iter().find(|x| &x.name() == name).unwrap();
+        step.check()?;
+        let step_params = params.get(name).cloned().unwrap_or_default();
+        resolve_params(&step.metadata.params, &step_params).with_context(|| {
+            format!(
+                "Invalid parameters for the pipe '{}'. Set them with `--set {}.<name>=<value>`.",
+                name, name
+            )
+        })?;
+    }
+
+    let total = order.len();
+    let mut extra_args = options.extra_args;
+    for (i, name) in order.iter().enumerate() {
+        let step = pipes.iter().find(|x| &x.name() == name).unwrap().clone();
+        let step = if options.local { step.drop_env() } else { step };
+        let is_last = i == total - 1;
+
+        eprintln!("▶️  Step {}/{}: {}", i + 1, total, name);
+        // The steps before might have made new input files
+        config.forget_profiles();
+        kerblam_run_project(
+            config.clone(),
+            step,
+            &current_dir().unwrap(),
+            options.profile.clone(),
+            params.remove(name).unwrap_or_default(),
+            options.skip_build_cache,
+            if is_last { extra_args.take() } else { None },
+        )
+        .with_context(|| {
+            let skipped = if is_last {
+                String::new()
+            } else {
+                format!(" The {} step(s) after it were not run.", total - i - 1)
+            };
+            format!("Step {}/{} ('{}') failed.{}", i + 1, total, name, skipped)
+        })?;
+    }
+
+    Ok(())
+}
+
+/// How a step of a pipeline went
+enum StepStatus {
+    Done,
+    Failed,
+    Skipped,
+}
+
+impl Display for StepStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let status = match self {
+            Self::Done => "✅ done",
+            Self::Failed => "❌ failed",
+            Self::Skipped => "⏭️  skipped",
+        };
+        write!(f, "{}", status)
+    }
+}
+
+/// Run the steps of a pipeline in order, stopping at the first one that fails
+///
+/// The steps use the profile given to `kerblam run`, unless they set their
+/// own. A summary of all the steps is shown at the end, even on failure.
+fn run_pipeline(mut config: KerblamTomlOptions, name: &str, options: RunCommand) -> Result<()> {
+    if config.pipes()?.iter().any(|x| x.name == name) {
+        bail!(
+            "'{}' is the name of both a pipe and a pipeline. Rename one of them.",
+            name
+        )
+    }
+    if options.desc {
+        print_md(&format!(
+            "Pipeline '{}':\n\n{}",
+            name, config.pipelines[name]
+        ));
+        return Ok(());
+    }
+    if options.with_deps || !options.params.is_empty() || options.extra_args.is_some() {
+        bail!("Pipelines cannot be run with `--with-deps`, `--set` or extra arguments.")
+    }
+
+    let steps = config.pipeline_steps(name)?;
+    let total = steps.len();
+    let mut summary: Vec<(String, StepStatus, Option<Duration>)> = vec![];
+    let mut failure: Option<anyhow::Error> = None;
+    for (i, (step, pipe)) in steps.into_iter().enumerate() {
+        let label = step.label();
+        if failure.is_some() {
+            summary.push((label, StepStatus::Skipped, None));
+            continue;
+        }
+        let pipe = if options.local { pipe.drop_env() } else { pipe };
+
+        eprintln!("▶️  Step {}/{}: {}", i + 1, total, label);
+        // The steps before might have made new input files
+        config.forget_profiles();
+        let start = Instant::now();
+        let result = kerblam_run_project(
+            config.clone(),
+            pipe,
+            &current_dir().unwrap(),
+            step.profile.or(options.profile.clone()),
+            vec![],
+            options.skip_build_cache,
+            None,
+        );
+        let status = match result {
+            Ok(()) => StepStatus::Done,
+            Err(e) => {
+                failure = Some(e.context(format!(
+                    "Step {}/{} ('{}') of the pipeline '{}' failed.",
+                    i + 1,
+                    total,
+                    step.pipe,
+                    name
+                )));
+                StepStatus::Failed
+            }
+        };
+        summary.push((label, status, Some(start.elapsed())));
+    }
+
+    eprintln!("\n{}", pipeline_summary(name, &summary));
+
+    match failure {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Format a table with the status and duration of the steps of a pipeline
+fn pipeline_summary(name: &str, steps: &[(String, StepStatus, Option<Duration>)]) -> String {
+    let format_elapsed = |duration: &Duration| {
+        if duration.as_secs() < 60 {
+            format!("{:.1}s", duration.as_secs_f64())
+        } else {
+            format_duration(*duration)
+        }
+    };
+    let width = steps
+        .iter()
+        .map(|(label, _, _)| label.chars().count())
+        .chain([4])
+        .max()
+        .unwrap();
+
+    let mut lines = vec![
+        format!("📋 Summary of the pipeline '{}':", name),
+        format!("    #  {:<width$}  {:<8}  Status", "Step", "Duration"),
+    ];
+    for (i, (label, status, duration)) in steps.iter().enumerate() {
+        let duration = duration.as_ref().map(format_elapsed).unwrap_or("-".into());
+        lines.push(format!(
+            "    {:<2} {:<width$}  {:<8}  {}",
+            i + 1,
+            label,
+            duration,
+            status
+        ));
+    }
+
+    lines.join("\n")
+}
+
+/// Move the files of a profile in place
+///
+/// Every move is recorded in the `journal` before it is made, so that the
+/// caller can undo them if this fails (see `abort_profile`).
+/// Returns the file movers needed to undo the moves.
+fn apply_moved_profile(
+    config: &KerblamTomlOptions,
+    profile: String,
+    journal: &Journal,
+) -> Result<Vec<FileMover>> {
+    // This should mean that there is a profile with the same name in the
+    // config...
+    let profile_paths = extract_profile_paths(config, profile.as_str(), true)?;
+
+    // Rename the paths that we found, stopping at the first failure.
+    // The moves that were made are undone by the caller, from the journal.
+    // The timestamps were already updated, if needed: see `touch_changed_paths`
+    let mut movers: Vec<FileMover> = vec![];
+    for mover in profile_paths {
+        journal.record(JournalEntry::Rename {
+            from: mover.get_from(),
+            to: mover.get_to(),
+        })?;
+        movers.push(
+            mover
+                .rename(false)
+                .context("A profiled path failed to be moved")?,
+        );
+    }
+
+    Ok(movers)
+}
+
+/// Put the files of a profile in place through an overlay of symlinks
+///
+/// Containerized runs get the overlay bind-mounted in place of the input
+/// directory, while local runs get the profiled paths in the input directory
+/// temporarily replaced by symlinks. The original files are never moved out
+/// of the input directory.
+fn apply_symlinked_profile(
+    config: &KerblamTomlOptions,
+    profile: &str,
+    executor: &mut Executor,
+    journal: &Journal,
+) -> Result<Overlay> {
+    let input_dir = config.input_data_dir();
+    let swaps = expand_profile_paths(config, profile)?;
+
+    let missing: Vec<String> = swaps
+        .iter()
+        .filter_map(|(_, target)| target.as_ref())
+        .filter(|target| !input_dir.join(target).exists())
+        .map(|x| format!("\t- {}", x.to_string_lossy()))
+        .collect();
+    if !missing.is_empty() {
+        bail!(
+            "Failed to find some profiles files:\n{}",
+            missing.join("\n")
+        )
+    }
+
+    if executor.mounts_data_dirs() {
+        let overlay_dir = current_dir()?.join(".kerblam/overlay");
+        let overlay = Overlay::build(overlay_dir, input_dir, &swaps, journal)?;
+        executor.add_mount_overrides(overlay.mount_overrides());
+        Ok(overlay)
+    } else {
+        Overlay::swap_locally(input_dir, &swaps, journal)
+    }
+}
+
+/// Return the (location, file) pairs of what a profile puts in the input dir
+///
+/// The file is what the workflow will find at the location during the run,
+/// or None if the profile hides the location.
+fn served_paths(
+    config: &KerblamTomlOptions,
+    profile: &str,
+) -> Result<Vec<(PathBuf, Option<PathBuf>)>> {
+    let input_dir = config.input_data_dir();
+    Ok(expand_profile_paths(config, profile)?
+        .into_iter()
+        .map(|(original, target)| (input_dir.join(original), target.map(|x| input_dir.join(x))))
+        .collect())
+}
+
+/// Send the output and intermediate directories where the profile wants them
+///
+/// Like with overlays, containerized runs get the new directories
+/// bind-mounted, while local runs get the data directories temporarily
+/// replaced by symlinks.
+fn apply_redirects(
+    config: &KerblamTomlOptions,
+    profile: &str,
+    executor: &mut Executor,
+    journal: &Journal,
+) -> Result<Vec<Redirect>> {
+    let mut redirects = vec![];
+    for (data_dir, target) in config.profile_redirects(profile)? {
+        let contained = config.data_dirs_inside(&data_dir);
+        let mut redirect = Redirect::new(data_dir, target, contained)?;
+        if executor.mounts_data_dirs() {
+            executor.add_mount_overrides(redirect.mount_overrides());
+        } else {
+            redirect.enable_locally(journal)?;
+        }
+        redirects.push(redirect);
+    }
+
+    Ok(redirects)
+}
+
+/// Undo a partially applied profile, after a failure
+///
+/// Everything that was done is in the journal, so we just replay it.
+fn abort_profile(journal: &Journal, error: anyhow::Error) -> anyhow::Error {
+    if let Err(e) = journal.replay() {
+        eprintln!("❌ Failed to undo the profile: {e:?}");
+        eprintln!("   Run `kerblam recover` to try again.");
+    }
+    error
+}
+
+/// Offer to undo the profile changes left behind by an interrupted run
+///
+/// The user is only asked if stdin is a terminal: otherwise (e.g. in batch
+/// jobs) nobody could answer, so this fails instead.
+fn recover_interrupted_run(journal: &Journal) -> Result<()> {
+    if journal.is_empty() {
+        return Ok(());
+    }
+
+    eprintln!("⚠️  A previous run was interrupted while a profile was in use, and the input data was not restored.");
+    if !stdin().is_terminal() || !bool::from(ask_for::<YesNo>("Restore it now?")) {
+        bail!("Refusing to run with a profile still in place. Run `kerblam recover` to restore the input data.")
+    }
+    journal.replay()?;
+    eprintln!("✅ Restored the input data.");
+
+    Ok(())
+}
+
+/// Pass parameters to a pipe: as make variables for makefiles, and as env
+/// vars otherwise
+fn add_params(
+    strategy: &ExecutionStrategy,
+    params: Vec<(String, String)>,
+    env_vars: &mut HashMap<String, String>,
+    args: &mut Vec<String>,
+) {
+    for (name, value) in params {
+        match strategy {
+            ExecutionStrategy::Make => args.push(format!("{}={}", name, value)),
+            _ => {
+                env_vars.insert(name, value);
+            }
+        }
+    }
+}
+
 pub fn kerblam_run_project(
     config: KerblamTomlOptions,
     pipe: Pipe,
     runtime_dir: &PathBuf,
     profile: Option<String>,
-    ignore_container: bool,
+    params: Vec<String>,
     skip_build_cache: bool,
     extra_args: Option<Vec<String>>,
 ) -> Result<()> {
-    let pipe = if ignore_container {
-        pipe.drop_env()
-    } else {
-        pipe
-    };
     log::debug!("Profile: {:?}", profile);
 
-    // Create an executor for later.
-    let executor: Executor = pipe.into_executor(runtime_dir)?;
+    pipe.check()?;
+    let metadata = pipe.metadata.clone();
+    if metadata.profile_required && profile.is_none() {
+        bail!(
+            "The pipe '{}' must be run with a data profile. Use `--profile <profile>`.",
+            pipe.name()
+        )
+    }
+    // The parameters set by the user, and not left to their default
+    let set_params: Vec<String> = params
+        .iter()
+        .filter_map(|x| x.split_once('='))
+        .map(|(name, _)| name.trim().to_string())
+        .collect();
+    let params = resolve_params(&metadata.params, &params)?;
 
-    // Handle renaming the input files if we are in a profile
-    let unwinding_paths: Vec<FileMover> = if let Some(profile) = profile.clone() {
-        // This should mean that there is a profile with the same name in the
-        // config...
-        let profile_paths = extract_profile_paths(&config, profile.as_str(), true)?;
-
-        // Check the cache (if there) what the last profile was.
-        // If it was this one, we should not update the file creation time
-        // as we move them around, or the make pipelines re-run from the
-        // beginning even if we did nothing to them
-        let cached_run = check_last_profile(profile);
-        let cached_run = cached_run.unwrap_or(false);
-        log::debug!("Checked cached profile: {}", cached_run);
-
-        // Rename the paths that we found
-        let move_results: Vec<Result<FileMover, anyhow::Error>> = profile_paths
-            .into_iter()
-            .map(|x| x.rename(!cached_run))
-            .collect();
-        // If they are all ok, return the vec
-        if move_results.iter().all(|x| x.is_ok()) {
-            move_results.into_iter().map(|x| x.unwrap()).collect()
-        } else {
-            // Not all is ok, unwind and bail out.
-            // I have to clone here as I need to consume the vector twice,
-            // but the toplevel vector cannot be cloned, so I clone and then
-            // ref deeper in. A bit clunky.
-            let unwindable: Vec<FileMover> = move_results
-                .iter()
-                .filter_map(|x| x.as_ref().ok())
-                .map(|x| x.to_owned())
-                .collect();
-            for mover in unwindable {
-                // I don't use the result for the same reason.
-                let _ = mover.rename(!cached_run);
-            }
+    // Create an executor for later.
+    let mut executor: Executor = pipe.into_executor(runtime_dir, &config.execution.interpreters)?;
+    executor.set_timeout(metadata.timeout);
 
-            let failed: Vec<anyhow::Error> =
-                move_results.into_iter().filter_map(|x| x.err()).collect();
+    // Undo what an interrupted run did to the input files, if anything
+    let journal = Journal::current();
+    recover_interrupted_run(&journal)?;
 
-            bail!(
-                "Some profiled paths failed to be moved: {}",
-                failed
-                    .into_iter()
-                    .map(|x| x.to_string())
-                    .collect::<Vec<String>>()
-                    .join("\n")
-            )
-        }
+    // Build the extra env vars and arguments that we want to use during
+    // the execution. The default values of the parameters come first, then
+    // the ones of the profile, then the ones given by the user (parameters
+    // and extra arguments), so that each can override the ones before.
+    let mut env_vars: HashMap<String, String> = HashMap::new();
+    let mut all_args: Vec<String> = vec![];
+    let (set_params, default_params): (Vec<_>, Vec<_>) = params
+        .into_iter()
+        .partition(|(name, _)| set_params.contains(name));
+    add_params(
+        executor.strategy(),
+        default_params,
+        &mut env_vars,
+        &mut all_args,
+    );
+    if let Some(profile) = &profile {
+        env_vars.extend(config.profile_env(profile)?);
+        all_args.extend(config.profile_extra_args(profile)?);
+        env_vars.insert("KERBLAM_PROFILE".to_string(), profile.to_owned());
+    }
+    add_params(
+        executor.strategy(),
+        set_params,
+        &mut env_vars,
+        &mut all_args,
+    );
+    all_args.extend(extra_args.unwrap_or_default());
+    let extra_args = if all_args.is_empty() {
+        None
     } else {
-        // If we are not in a profile now, but we were before, we should
-        // re-touch all the old profile paths just to be safe that the
-        // whole pipeline is re-run again with the new data
-        let last_cache = get_cache();
-        if last_cache
-            .clone()
-            .is_some_and(|x| x.last_executed_profile.is_some())
-        {
-            log::debug!("Should re-touch profile files.");
-            // We can avoid checking if all files exist or in general if the
-            // profile is valid, since we just touch the existing files and
-            // that is it - we don't enact the profile.
-            let profile_paths = extract_profile_paths(
-                &config,
-                &last_cache.unwrap().last_executed_profile.unwrap(),
-                false,
-            )
-            .unwrap_or_else(|_| {
-                log::warn!("Could not find old profile paths. Skipping re-touching.");
+        Some(all_args)
+    };
+
+    // Touch the files that the workflow will see in place of the ones
+    // it saw last time, if their content is different, so that make
+    // re-runs only what is needed.
+    let served = match &profile {
+        Some(profile) => served_paths(&config, profile)?,
+        None => vec![],
+    };
+    let served_states = touch_changed_paths(&served)?;
+
+    // Handle renaming the input files if we are in a profile
+    let mut overlay: Option<Overlay> = None;
+    let mut redirects: Vec<Redirect> = vec![];
+    let unwinding_paths: Vec<FileMover> = if let Some(profile) = profile.clone() {
+        let mode = config.profile_mode(&profile)?;
+        redirects = apply_redirects(&config, &profile, &mut executor, &journal)
+            .map_err(|e| abort_profile(&journal, e))?;
+        match mode {
+            ProfileMode::Move => apply_moved_profile(&config, profile, &journal)
+                .map_err(|e| abort_profile(&journal, e))?,
+            ProfileMode::Symlink => {
+                overlay = Some(
+                    apply_symlinked_profile(&config, &profile, &mut executor, &journal)
+                        .map_err(|e| abort_profile(&journal, e))?,
+                );
                 vec![]
-            });
-
-            for mover in profile_paths {
-                log::debug!("Touching {:?}", &mover.clone().get_from());
-                match update_timestamps(&mover.clone().get_from()) {
-                    Ok(_) => (),
-                    Err(e) => {
-                        log::warn!(
-                            "Cannot touch {:?}: {:?}. Was the file deleted? Ignoring it.",
-                            &mover.clone().get_from(),
-                            e
-                        )
-                    }
-                }
             }
-
-            // We are done. We can delete the last profile.
-            let _ = delete_last_profile();
         }
-
+    } else {
         vec![]
     };
 
-    // Build the extra env vars that we want to set during the execution
-    let env_vars: HashMap<String, String> = if let Some(profile) = profile {
-        HashMap::from([("KERBLAM_PROFILE".to_string(), profile)])
-    } else {
-        HashMap::new()
-    };
+    // The profile is in place: remember what we served, for the next run
+    if let Err(e) = save_profiled_paths(served_states, profile.clone()) {
+        log::warn!("Failed to save the profiled paths to the cache: {e:?}");
+    }
 
     // Execute the executor
     let runtime_result = executor.execute(&config, env_vars, skip_build_cache, extra_args);
 
     // Undo the input file renaming
+    let mut restored = true;
     if !unwinding_paths.is_empty() {
         log::info!("Undoing profile...");
         for item in unwinding_paths.into_iter().rev() {
-            // If this worked before, it should work now, that is why I discard the
-            // result...
-            // TODO: This might be a bad idea.
-            //
             // We can skip updating timestamps at this stage
-            let _ = item.rename(false);
+            if let Err(e) = item.rename(false) {
+                eprintln!("❌ Failed to undo a profile move: {e:?}");
+                restored = false;
+            }
         }
     }
 
     // Try and destroy the symlinks
+    if let Some(overlay) = overlay {
+        log::info!("Removing profile overlay...");
+        if let Err(e) = overlay.teardown() {
+            eprintln!("❌ Failed to remove the profile overlay: {e:?}");
+            restored = false;
+        }
+    }
+
+    // The declared outputs can only be checked if they were not redirected
+    let check_outputs = redirects.is_empty();
+
+    // Put the output and intermediate directories back
+    for redirect in redirects.into_iter().rev() {
+        if let Err(e) = redirect.teardown() {
+            eprintln!("❌ Failed to undo a profile redirect: {e:?}");
+            restored = false;
+        }
+    }
+
+    if restored {
+        journal.clear()?;
+    } else {
+        eprintln!("   Run `kerblam recover` to try again.");
+    }
 
     // Return either an error or OK, if the pipeline finished appropriately
     // or crashed and burned.
-    if runtime_result.is_ok() {
-        match runtime_result.unwrap() {
+    match runtime_result {
+        Ok(result) => match result {
             Some(res) => {
                 if res.success() {
+                    if check_outputs {
+                        warn_missing_outputs(&metadata);
+                    }
                     Ok(())
                 } else {
                     Err(anyhow!("Process exited with error: {res:?}"))
                 }
             }
             None => Err(anyhow!("Process killed.")),
-        }
-    } else {
-        Err(anyhow!("Process exited."))
+        },
+        // E.g. the container could not be built
+        Err(e) => Err(e),
+    }
+}
+
+/// Warn the user about the declared outputs of a pipe that do not exist
+fn warn_missing_outputs(metadata: &PipeMetadata) {
+    let missing: Vec<String> = metadata
+        .outputs
+        .iter()
+        .filter(|x| !x.exists())
+        .map(|x| format!("\t- {}", x.to_string_lossy()))
+        .collect();
+    if !missing.is_empty() {
+        eprintln!(
+            "⚠️  The pipe finished, but some of its outputs were not created:\n{}",
+            missing.join("\n")
+        );
     }
 }
@@ -0,0 +1,334 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Result};
+
+use super::{run_protected_command, CommandResult};
+use crate::cache::get_cache_path;
+use crate::utils::normalize_path;
+
+/// The folder with the Apptainer images of the current project
+///
+/// This is next to the cache file of the project, and not in the project
+/// itself, or else the images would be copied into the next ones by
+/// `%files` sections like `. /app`.
+fn images_dir() -> Result<PathBuf> {
+    let cache = get_cache_path()?;
+    let name = cache.file_name().unwrap().to_string_lossy().to_string();
+    let dir = cache.with_file_name(format!("{}_images", name));
+    std::fs::create_dir_all(&dir)?;
+
+    Ok(dir)
+}
+
+/// The path to the Apptainer image with this name
+pub fn image_path(name: &str) -> Result<PathBuf> {
+    Ok(images_dir()?.join(format!("{}.sif", name)))
+}
+
+/// The image that Apptainer should run for a container tag
+///
+/// Image files and references with a transport (e.g. `docker://`,
+/// `oras://`) are used as they are. Other tags are pulled from the
+/// docker registry that they refer to.
+pub fn image_reference(tag: &str) -> String {
+    if tag.contains("://") || Path::new(tag).is_file() {
+        tag.to_string()
+    } else {
+        format!("docker://{}", tag)
+    }
+}
+
+/// Pull an existing image to a file, if it was not pulled before, and
+/// return the path to the file
+///
+/// If `offline`, the image is never pulled, and this fails if it was not
+/// pulled before.
+pub fn pull_image(command: &str, reference: &str, offline: bool) -> Result<PathBuf> {
+    let name: String = reference
+        .chars()
+        .map(|x| if x.is_ascii_alphanumeric() { x } else { '_' })
+        .collect();
+    let image = images_dir()?.join(format!("{}.sif", name));
+    if image.exists() {
+        return Ok(image);
+    }
+    if offline {
+        bail!(
+            "The image '{}' is not available locally, and cannot be pulled in offline mode. Run without offline mode once to pull it.",
+            reference
+        )
+    }
+
+    let builder = || {
+        Command::new(command)
+            .arg("pull")
+            .arg(&image)
+            .arg(image_reference(reference))
+            .stdout(Stdio::inherit())
+            .stdin(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .expect("Failed to spawn the pull process.")
+    };
+    match run_protected_command(builder)? {
+        CommandResult::Exited { res } if res.success() => Ok(image),
+        _ => bail!("Failed to pull the image '{}'", reference),
+    }
+}
+
+/// The sections of an Apptainer definition file, as they are filled in
+#[derive(Default)]
+struct Definition {
+    from: Option<String>,
+    files: Vec<String>,
+    environment: Vec<String>,
+    post: Vec<String>,
+    labels: Vec<String>,
+    entrypoint: Option<String>,
+    cmd: Option<String>,
+}
+
+/// Read the arguments of an instruction in exec form, like `["make", "all"]`
+///
+/// Returns None if the arguments are in shell form.
+fn exec_form(args: &str) -> Option<Vec<String>> {
+    if !args.starts_with('[') {
+        return None;
+    }
+    serde_json::from_str(args).ok()
+}
+
+/// Quote an argument for the shell, if needed
+fn quote(arg: &str) -> String {
+    let safe = arg
+        .chars()
+        .all(|x| x.is_ascii_alphanumeric() || "-_./=:@%+,".contains(x));
+    if safe && !arg.is_empty() {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+/// Turn the arguments of an instruction in a shell command line
+fn shell_command(args: &str) -> String {
+    match exec_form(args) {
+        Some(args) => args.iter().map(|x| quote(x)).collect::<Vec<_>>().join(" "),
+        None => args.to_string(),
+    }
+}
+
+/// Convert the content of a Dockerfile to an Apptainer definition file
+///
+/// Only the common instructions are supported: `FROM`, `RUN`, `ENV`,
+/// `WORKDIR`, `COPY`, `ADD` (of local files), `LABEL`, `ENTRYPOINT` and
+/// `CMD`. Instructions that only matter to docker (e.g. `EXPOSE`) are
+/// skipped, while the others (e.g. multi-stage builds or `USER`) fail.
+///
+/// Note that Apptainer copies all the files before running any command.
+pub fn dockerfile_to_definition(content: &str) -> Result<String> {
+    // Join the lines continued with a backslash
+    let mut instructions: Vec<String> = vec![];
+    let mut current = String::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.strip_suffix('\\') {
+            Some(start) => current.push_str(&format!("{} ", start.trim())),
+            None => {
+                current.push_str(line);
+                instructions.push(std::mem::take(&mut current));
+            }
+        }
+    }
+    if !current.is_empty() {
+        instructions.push(current);
+    }
+
+    let mut definition = Definition::default();
+    let mut workdir = PathBuf::from("/");
+    for instruction in instructions {
+        let (keyword, args) = instruction
+            .split_once(char::is_whitespace)
+            .map(|(keyword, args)| (keyword, args.trim()))
+            .unwrap_or((&instruction, ""));
+
+        match keyword.to_uppercase().as_str() {
+            "FROM" => {
+                if definition.from.is_some() {
+                    bail!("Multi-stage builds are not supported")
+                }
+                let image = args.split_whitespace().find(|x| !x.starts_with("--"));
+                match image {
+                    Some(image) => definition.from = Some(image.to_string()),
+                    None => bail!("The FROM instruction has no image"),
+                }
+            }
+            "RUN" => definition.post.push(shell_command(args)),
+            "ENV" => {
+                let first = args.split_whitespace().next().unwrap_or_default();
+                let export = if first.contains('=') {
+                    format!("export {}", args)
+                } else {
+                    let value = args[first.len()..].trim();
+                    format!("export {}={}", first, quote(value))
+                };
+                // Later commands in the build also see the variables
+                definition.environment.push(export.clone());
+                definition.post.push(export);
+            }
+            "WORKDIR" => {
+                workdir = normalize_path(&workdir.join(args));
+                let path = quote(&workdir.to_string_lossy());
+                definition.post.push(format!("mkdir -p {}", path));
+                definition.post.push(format!("cd {}", path));
+            }
+            "COPY" | "ADD" => {
+                let mut paths: Vec<String> = match exec_form(args) {
+                    Some(x) => x,
+                    None => args.split_whitespace().map(|x| x.to_string()).collect(),
+                };
+                if paths.iter().any(|x| x.starts_with("--from")) {
+                    bail!("Multi-stage builds are not supported")
+                }
+                paths.retain(|x| !x.starts_with("--"));
+                if paths.iter().any(|x| x.contains("://")) {
+                    bail!("Cannot ADD remote files")
+                }
+                let destination = match paths.pop() {
+                    Some(x) if !paths.is_empty() => normalize_path(&workdir.join(x)),
+                    _ => bail!(
+                        "The {} instruction needs a source and a destination",
+                        keyword
+                    ),
+                };
+                for source in paths {
+                    definition
+                        .files
+                        .push(format!("{} {}", source, destination.to_string_lossy()));
+                }
+            }
+            "LABEL" => {
+                for label in args.split_whitespace() {
+                    if let Some((key, value)) = label.split_once('=') {
+                        definition
+                            .labels
+                            .push(format!("{} {}", key, value.trim_matches('"')));
+                    }
+                }
+            }
+            "ENTRYPOINT" => definition.entrypoint = Some(shell_command(args)),
+            "CMD" => definition.cmd = Some(shell_command(args)),
+            "EXPOSE" | "VOLUME" | "HEALTHCHECK" | "STOPSIGNAL" | "MAINTAINER" => {
+                log::warn!("Skipping the {} instruction for Apptainer", keyword)
+            }
+            other => bail!("The {} instruction is not supported", other),
+        }
+    }
+
+    let from = match definition.from {
+        Some(x) => x,
+        None => bail!("There is no FROM instruction"),
+    };
+    let mut text = format!("Bootstrap: docker\nFrom: {}\n", from);
+    let mut section = |name: &str, lines: &[String]| {
+        if !lines.is_empty() {
+            text.push_str(&format!("\n%{}\n", name));
+            for line in lines {
+                text.push_str(&format!("    {}\n", line));
+            }
+        }
+    };
+    section("files", &definition.files);
+    section("environment", &definition.environment);
+    section("post", &definition.post);
+    section("labels", &definition.labels);
+    // Like docker, run the entrypoint with the command as default arguments
+    let runscript = match (definition.entrypoint, definition.cmd) {
+        (Some(entrypoint), Some(cmd)) => Some(format!(
+            "if [ $# -gt 0 ]; then exec {} \"$@\"; else exec {} {}; fi",
+            entrypoint, entrypoint, cmd
+        )),
+        (Some(entrypoint), None) => Some(format!("exec {} \"$@\"", entrypoint)),
+        (None, Some(cmd)) => Some(format!("exec {}", cmd)),
+        (None, None) => None,
+    };
+    if let Some(runscript) = runscript {
+        section(
+            "runscript",
+            &[
+                format!("cd {}", quote(&workdir.to_string_lossy())),
+                runscript,
+            ],
+        );
+    }
+
+    Ok(text)
+}
+
+/// Get the definition file to build an Apptainer image from a container file
+///
+/// Definition files (`.def`) are used as they are, while dockerfiles are
+/// converted and saved next to the images (see `images_dir`).
+pub fn definition_file(container_file: &Path, name: &str) -> Result<PathBuf> {
+    if container_file.extension().is_some_and(|x| x == "def") {
+        return Ok(container_file.to_path_buf());
+    }
+
+    let content = std::fs::read_to_string(container_file)?;
+    let definition = dockerfile_to_definition(&content).map_err(|e| {
+        e.context(format!(
+            "Cannot convert {} to an Apptainer definition file. Write a '.def' file for it instead.",
+            container_file.to_string_lossy()
+        ))
+    })?;
+    let path = images_dir()?.join(format!("{}.def", name));
+    std::fs::write(&path, definition)?;
+
+    Ok(path)
+}
+
+#[test]
+fn test_dockerfile_to_definition() {
+    let dockerfile = r#"
+# A comment
+FROM --platform=linux/amd64 ubuntu:22.04
+ENV DEBIAN_FRONTEND noninteractive
+RUN apt-get update && \
+    apt-get install -y make
+WORKDIR /app
+COPY . .
+LABEL maintainer="me"
+ENTRYPOINT ["/bin/bash", "./replay.sh"]
+"#;
+    let expected = r#"Bootstrap: docker
+From: ubuntu:22.04
+
+%files
+    . /app
+
+%environment
+    export DEBIAN_FRONTEND=noninteractive
+
+%post
+    export DEBIAN_FRONTEND=noninteractive
+    apt-get update && apt-get install -y make
+    mkdir -p /app
+    cd /app
+
+%labels
+    maintainer me
+
+%runscript
+    cd /app
+    exec /bin/bash ./replay.sh "$@"
+"#;
+    assert_eq!(dockerfile_to_definition(dockerfile).unwrap(), expected);
+
+    assert!(dockerfile_to_definition("FROM a\nFROM b").is_err());
+    assert!(dockerfile_to_definition("FROM a\nUSER me").is_err());
+    assert!(dockerfile_to_definition("RUN echo hi").is_err());
+}
@@ -2,20 +2,28 @@ use core::panic;
 use std::collections::HashMap;
 use std::env::current_dir;
 use std::fs;
-use std::io::{stdout, IsTerminal};
+use std::io::{stdin, stdout, IsTerminal};
+use std::os::unix::process::CommandExt;
 use std::path::{Path, PathBuf};
 use std::process::{Child, Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant};
 
-use crate::options::KerblamTomlOptions;
-use crate::utils::update_timestamps;
+use crate::options::{ContainerBackend, KerblamTomlOptions, DEFAULT_WORKDIR};
+use crate::utils::{get_salt, update_timestamps};
 
-use anyhow::{anyhow, bail, Context, Result};
+use anyhow::{bail, Result};
 use crossbeam_channel::{bounded, Receiver};
 use lazy_static::lazy_static;
 
+mod apptainer;
+mod overlay;
+mod redirect;
 mod strategies;
 
-use strategies::ExecutionStrategy;
+pub use apptainer::image_reference;
+pub use overlay::{stash_path, Overlay};
+pub use redirect::Redirect;
+pub use strategies::ExecutionStrategy;
 
 // TODO: I think we can add all cleanup code to `Drop`, so that a lot of these
 // functions can be simplified a lot.
@@ -57,10 +65,33 @@ pub struct Executor {
     /// A `FileMover` that targets the file to execute.
     /// The analysis will be based on the `to` field.
     target: FileMover,
-    /// Optionally, the path to the container file to execute inside of
-    env: Option<PathBuf>,
+    /// Optionally, the container to execute inside of
+    env: Option<Environment>,
     /// The execution strategy. Depends on which target to execute.
     strategy: ExecutionStrategy,
+    /// Changes to the bind mounts of containerized runs
+    mounts: MountOverrides,
+    /// How long the execution can last before being stopped
+    timeout: Option<Duration>,
+}
+
+/// The container that an executor runs in
+#[derive(Debug, Clone, PartialEq)]
+pub enum Environment {
+    /// A container file to build: a dockerfile or an Apptainer definition
+    /// file (or an `.image` file, but those are read to an `Image` first)
+    File(PathBuf),
+    /// A reference to an existing image, like `ghcr.io/org/tool:1.4`
+    Image(String),
+}
+
+/// Changes to how the data directories are bind-mounted in containers
+#[derive(Debug, Clone, Default)]
+pub struct MountOverrides {
+    /// Data directories that should be replaced by other host directories
+    pub replace: HashMap<PathBuf, PathBuf>,
+    /// Extra host paths to mount read-only, at the same path in the container
+    pub read_only: Vec<PathBuf>,
 }
 
 /// Stringify a something that can be iterated upon of non-strings.
@@ -75,9 +106,16 @@ macro_rules! stringify {
 /// This generates a binding for each of the input/output/intermediate data
 /// dirs and makes a `-v` argument that can be passed to the backend in order
 /// to mimick the local file system in the container.
+/// The same strings work for the `--bind` options of Apptainer.
+///
+/// The `overrides` can replace the local folder of some data dirs, and add
+/// other read-only mounts.
 ///
 /// Returns a vector of strings with the various `-v source:sink` options.
-pub fn generate_bind_mount_strings(config: &KerblamTomlOptions) -> Vec<String> {
+pub fn generate_bind_mount_strings(
+    config: &KerblamTomlOptions,
+    overrides: &MountOverrides,
+) -> Vec<String> {
     let mut result: Vec<String> = vec![];
     let root = current_dir().unwrap();
 
@@ -89,14 +127,18 @@ pub fn generate_bind_mount_strings(config: &KerblamTomlOptions) -> Vec<String> {
 
     let host_workdir = match config.execution.workdir.clone() {
         Some(p) => format!("{}", p.to_string_lossy()),
-        None => "/".into(),
+        None => DEFAULT_WORKDIR.into(),
     };
 
     log::debug!("Host workdir set to {host_workdir:?}");
 
     for dir in dirs {
         // the folder here, in the local file system
-        let local = dir.to_string_lossy();
+        let local = overrides
+            .replace
+            .get(&dir)
+            .unwrap_or(&dir)
+            .to_string_lossy();
         // the folder in the host container
         let host = dir.strip_prefix(&root).unwrap().to_string_lossy();
         let host = format!("{host_workdir}/{host}");
@@ -104,11 +146,43 @@ pub fn generate_bind_mount_strings(config: &KerblamTomlOptions) -> Vec<String> {
         result.push(format!("{}:{}", local, host))
     }
 
+    for path in &overrides.read_only {
+        let path = path.to_string_lossy();
+        result.push(format!("{}:{}:ro", path, path))
+    }
+
     log::debug!("Generated bind mount strings: {:?}", result);
 
     result
 }
 
+/// Generate the command that runs a Nextflow pipeline in the current project
+///
+/// The work directory of Nextflow is put in the intermediate data dir, and
+/// the input and output data dirs are given to the pipeline as the
+/// `input_dir` and `outdir` parameters.
+fn nextflow_command(config: &KerblamTomlOptions, pipeline: &Path) -> Vec<String> {
+    let root = current_dir().unwrap();
+    let relative = |path: PathBuf| -> String {
+        path.strip_prefix(&root)
+            .unwrap_or(&path)
+            .to_string_lossy()
+            .to_string()
+    };
+
+    vec![
+        "nextflow".to_string(),
+        "run".to_string(),
+        pipeline.to_string_lossy().to_string(),
+        "-work-dir".to_string(),
+        relative(config.intermediate_data_dir().join("nextflow")),
+        "--input_dir".to_string(),
+        relative(config.input_data_dir()),
+        "--outdir".to_string(),
+        relative(config.output_data_dir()),
+    ]
+}
+
 impl Executor {
     /// Execute this executor based on its data
     ///
@@ -128,64 +202,132 @@ impl Executor {
         extra_args: Option<Vec<String>>,
     ) -> Result<Option<ExitStatus>> {
         let mut cleanup: Vec<PathBuf> = vec![];
-
-        let mut command_args = if self.env.is_some() {
-            // This is a containerized run
-            let backend: String = config.execution.backend.clone().into();
-            let runtime_name = self.build_env(&backend, skip_build_cache)?;
-            let mut partial: Vec<String> = if stdout().is_terminal() {
-                // We are in a terminal. Run interactively
-                stringify![vec![&backend, "run", "--rm", "-it"]]
-            } else {
-                // We are not in a terminal. Run normally
-                stringify![vec![&backend, "run", "--rm"]]
+        let backend: String = config.execution.backend.clone().into();
+        // The name of the docker container, to stop it if the run is killed
+        let mut container_name: Option<String> = None;
+
+        let mut command_args = if self.strategy.manages_containers() {
+            // The workflow manager runs locally, and handles the container
+            // (if any) by itself.
+            let runtime_name = match self.env {
+                Some(_) => Some(self.build_env(
+                    &config.execution.backend,
+                    skip_build_cache,
+                    config.execution.offline,
+                )?),
+                None => None,
             };
-            // We need to bind-mount the same data dirs as specified in the options
-            let mounts = generate_bind_mount_strings(config);
-            for mount in mounts {
-                partial.extend(vec!["-v".to_string(), mount].into_iter())
+            cleanup.push(self.target.copy()?);
+
+            let mut partial = nextflow_command(config, &self.target.to);
+            if let Some(runtime_name) = runtime_name {
+                partial.extend(vec![format!("-with-{}", backend), runtime_name]);
             }
 
-            // Add the correct entrypoint override
+            partial
+        } else if self.env.is_some() {
+            // This is a containerized run
+            let runtime_name = self.build_env(
+                &config.execution.backend,
+                skip_build_cache,
+                config.execution.offline,
+            )?;
             let workdir = config.execution.workdir.clone();
             let workdir = match workdir {
                 Some(p) => p,
-                None => PathBuf::from("/"),
+                None => PathBuf::from(DEFAULT_WORKDIR),
             };
             let workdir = workdir.to_string_lossy();
-            let execution_command: Vec<String> = match self.strategy {
-                ExecutionStrategy::Make => stringify!(vec![
-                    "--entrypoint",
-                    "make",
-                    &runtime_name,
-                    "-f",
-                    "executor",
-                    "-C",
-                    &workdir
-                ]),
-                ExecutionStrategy::Shell => stringify!(vec![
-                    "--entrypoint",
-                    "bash",
-                    &runtime_name,
-                    &format!("{}/executor", workdir)
-                ]),
-            };
+            // We need to bind-mount the same data dirs as specified in the options
+            let mut mounts = generate_bind_mount_strings(config, &self.mounts);
+            let is_image = matches!(self.env, Some(Environment::Image(_)));
+            if is_image {
+                // The executor file was not copied in the image by a build
+                cleanup.push(self.target.copy()?);
+                mounts.push(format!(
+                    "{}:{}/executor:ro",
+                    self.target.to.to_string_lossy(),
+                    workdir
+                ));
+            }
+            let execution_command = self.container_command(&workdir);
+
+            if config.execution.backend.is_docker_like() {
+                // Runs with a timeout are in their own process group (see
+                // below), that cannot use the terminal interactively.
+                let mut partial: Vec<String> = if stdout().is_terminal() && self.timeout.is_none() {
+                    // We are in a terminal. Run interactively
+                    stringify![vec![&backend, "run", "--rm", "-it"]]
+                } else {
+                    // We are not in a terminal. Run normally
+                    stringify![vec![&backend, "run", "--rm"]]
+                };
+                let name = format!("kerblam_{}", get_salt(12));
+                partial.extend(["--name".to_string(), name.clone()]);
+                container_name = Some(name);
+                for mount in mounts {
+                    partial.extend(vec!["-v".to_string(), mount].into_iter())
+                }
+                // Built images are expected to have the workdir set already
+                if is_image {
+                    partial.extend(["-w".to_string(), workdir.to_string()])
+                }
+                // The extra env vars are set for the backend process, so we just
+                // ask the backend to forward them to the container.
+                let mut env_names: Vec<&String> = env_vars.keys().collect();
+                env_names.sort_unstable();
+                for name in env_names {
+                    partial.extend(["-e".to_string(), name.to_owned()])
+                }
 
-            partial.extend(execution_command);
+                // Add the correct entrypoint override
+                partial.extend(vec![
+                    "--entrypoint".to_string(),
+                    execution_command[0].clone(),
+                    runtime_name,
+                ]);
+                partial.extend(execution_command[1..].iter().cloned());
 
-            partial
+                partial
+            } else {
+                // Apptainer runs the command in the image file directly, and
+                // passes all the env vars of the backend process by itself.
+                let mut partial: Vec<String> =
+                    stringify![vec![&backend, "exec", "--no-home", "--pwd", &workdir]];
+                for mount in mounts {
+                    partial.extend(["--bind".to_string(), mount])
+                }
+                partial.push(runtime_name);
+                partial.extend(execution_command);
+
+                partial
+            }
         } else {
             // This is a normal run.
             // Move the executor file
             cleanup.push(self.target.copy()?);
 
-            match self.strategy {
+            match &self.strategy {
                 ExecutionStrategy::Make => {
                     stringify![vec!["make", "-f", self.target.to.to_str().unwrap()]]
                 }
                 ExecutionStrategy::Shell => {
                     stringify![vec!["bash", self.target.to.to_str().unwrap()]]
                 }
+                ExecutionStrategy::Snakemake => stringify![vec![
+                    "snakemake",
+                    "-s",
+                    self.target.to.to_str().unwrap(),
+                    "--cores",
+                    "all"
+                ]],
+
+                ExecutionStrategy::Interpreter(command) => {
+                    let mut args = command.clone();
+                    args.push(self.target.to.to_string_lossy().to_string());
+                    args
+                }
+                ExecutionStrategy::Nextflow => unreachable!(),
             }
         };
 
@@ -197,22 +339,36 @@ impl Executor {
         log::debug!("Executor command arguments: {:?}", command_args);
 
         let mut command = Command::new(&command_args[0]);
+        command.stdin(Stdio::inherit());
+        if self.timeout.is_some() {
+            // Run in a new process group, so that all the processes that the
+            // command starts can be stopped on timeout (see `kill_child`).
+            // Such a group cannot read from the terminal (it would be stopped
+            // by SIGTTIN until the timeout), so it gets no input instead.
+            command.process_group(0);
+            if stdin().is_terminal() {
+                command.stdin(Stdio::null());
+            }
+        }
 
         let builder = || {
             command
                 .args(&command_args[1..command_args.len()])
                 .stdout(Stdio::inherit())
                 .stderr(Stdio::inherit())
-                .stdin(Stdio::inherit())
                 .envs(env_vars)
                 .spawn()
                 .expect("Cannot retrieve command output!")
         };
 
-        let return_value = match run_protected_command(builder) {
+        let return_value = match run_protected_command_with_timeout(builder, self.timeout) {
             Ok(CommandResult::Exited { res }) => Ok(Some(res)), // We don't care if it succeeded.
             Ok(CommandResult::Killed) => {
                 eprintln!("\nChild process exited early. Continuing to cleanup...");
+                // Killing the backend process does not stop the container
+                if let Some(name) = container_name {
+                    stop_container(&backend, &name);
+                }
                 Ok(None)
             }
             Err(e) => {
@@ -230,19 +386,58 @@ impl Executor {
         return_value
     }
 
+    /// The command that runs the executor file in a container
+    ///
+    /// The first item is the program to run, used as the entrypoint of the
+    /// container. The `workdir` is where the executor file is in the container.
+    fn container_command(&self, workdir: &str) -> Vec<String> {
+        match &self.strategy {
+            ExecutionStrategy::Make => stringify!(vec!["make", "-f", "executor", "-C", workdir]),
+            ExecutionStrategy::Shell => stringify!(vec!["bash", &format!("{}/executor", workdir)]),
+            ExecutionStrategy::Snakemake => stringify!(vec![
+                "snakemake",
+                "-s",
+                &format!("{}/executor", workdir),
+                "-d",
+                workdir,
+                "--cores",
+                "all"
+            ]),
+            ExecutionStrategy::Interpreter(command) => {
+                let mut args = command.clone();
+                args.push(format!("{}/executor", workdir));
+                args
+            }
+            ExecutionStrategy::Nextflow => unreachable!(),
+        }
+    }
+
     /// Build the context of this executor and return its tag.
     ///
-    /// If the executor has no environment file, this function fails.
-    pub fn build_env(&self, backend: &str, no_cache: bool) -> Result<String> {
+    /// With Apptainer, the image is a file in the cache of the project, and
+    /// its path is returned instead. Dockerfiles are converted to definition
+    /// files first.
+    ///
+    /// Existing images are not built, but just checked (or, for Apptainer,
+    /// pulled to a file). If `offline`, they are never pulled, and this fails
+    /// if they are not available locally.
+    ///
+    /// If the executor has no environment, this function fails.
+    pub fn build_env(
+        &self,
+        backend: &ContainerBackend,
+        no_cache: bool,
+        offline: bool,
+    ) -> Result<String> {
         let mut cleanup: Vec<PathBuf> = vec![];
 
-        if self.env.is_none() {
-            bail!("Cannot build environment with no environment file.")
-        }
-
-        // Move the executor file and register it for cleanup
-        cleanup.push(self.target.copy()?);
-        let containerfile_path = self.env.clone().unwrap();
+        let containerfile_path = match &self.env {
+            None => bail!("Cannot build environment with no environment file."),
+            Some(Environment::Image(reference)) => {
+                return prepare_image(backend, reference, offline)
+            }
+            Some(Environment::File(path)) => path.clone(),
+        };
 
         let containerfile_name = containerfile_path
             .file_name()
@@ -252,32 +447,39 @@ impl Executor {
         let env_name: String = containerfile_name.split('.').take(1).collect();
         let env_name = env_name + "_kerblam_runtime";
 
-        let containerfile_path = containerfile_path.as_os_str().to_string_lossy().to_string();
-
-        let build_args: Vec<&str> = if no_cache {
-            vec![
+        let (build_args, runtime_name): (Vec<String>, String) = if backend.is_docker_like() {
+            let mut args: Vec<String> = stringify![vec![
                 "build",
+                // If the `self.env` path is not UTF-8 I'll eat my hat.
                 "-f",
-                containerfile_path.as_str(),
+                &containerfile_path.to_string_lossy(),
                 "--tag",
-                env_name.as_str(),
-                "--no-cache",
-                ".",
-            ]
+                &env_name
+            ]];
+            if no_cache {
+                args.push("--no-cache".to_string());
+            }
+            args.push(".".to_string());
+            (args, env_name)
         } else {
-            vec![
-                "build",
-                "-f",
-                containerfile_path.as_str(),
-                "--tag",
-                env_name.as_str(),
-                ".",
-            ]
+            // Apptainer has no build cache of its own, so we always rebuild
+            let definition = apptainer::definition_file(&containerfile_path, &env_name)?;
+            let image = apptainer::image_path(&env_name)?;
+            let mut args: Vec<String> = stringify![vec!["build", "--force"]];
+            if no_cache {
+                args.push("--disable-cache".to_string());
+            }
+            args.push(image.to_string_lossy().to_string());
+            args.push(definition.to_string_lossy().to_string());
+            (args, image.to_string_lossy().to_string())
         };
 
+        // Move the executor file and register it for cleanup
+        cleanup.push(self.target.copy()?);
+
+        let backend: String = backend.clone().into();
         let builder = || {
             Command::new(backend)
-                // If the `self.env` path is not UTF-8 I'll eat my hat.
                 .args(&build_args)
                 .stdout(Stdio::inherit())
                 .stdin(Stdio::inherit())
@@ -310,7 +512,7 @@ impl Executor {
             let _ = fs::remove_file(file);
         }
 
-        Ok(env_name)
+        Ok(runtime_name)
     }
 
     /// Create a new executor
@@ -322,12 +524,18 @@ impl Executor {
     /// - `root_path`: The folder where this executor will be executed
     /// - `executor`: The file to execute.
     ///   If `*.makefile`, use `ExecutionStrategy::Make`.
-    ///   If `*.sh`, use `ExecutionStrategy::Shell`
-    /// - `environment`: The containerfile to run this executor with, if any.
+    ///   If `*.sh`, use `ExecutionStrategy::Shell`.
+    ///   If `*.smk` or `*.snakefile`, use `ExecutionStrategy::Snakemake`.
+    ///   If `*.nf`, use `ExecutionStrategy::Nextflow`.
+    ///   Other scripts are run with an `ExecutionStrategy::Interpreter`
+    ///   (see `ExecutionStrategy::detect`).
+    /// - `environment`: The container to run this executor in, if any.
+    /// - `interpreters`: The interpreters to use for script extensions.
     pub fn create(
         root_path: impl AsRef<Path>,
         executor: impl AsRef<Path>,
-        environment: Option<impl AsRef<Path>>,
+        environment: Option<Environment>,
+        interpreters: &HashMap<String, String>,
     ) -> Result<Self> {
         let executor = executor.as_ref();
         let root_path = root_path.as_ref().to_path_buf();
@@ -337,47 +545,78 @@ impl Executor {
             to: root_path.join("executor"),
         };
 
-        let strategy = match executor.extension() {
-            None => {
-                return Err(anyhow!("Cannot determine execution strategy"))
-                    .with_context(|| "Specified executor has no extension")
-            }
-            Some(x) => match x.to_str().unwrap() {
-                "makefile" => ExecutionStrategy::Make,
-                "sh" => ExecutionStrategy::Shell,
-                ext => {
-                    return Err(anyhow!("Cannot determine execution strategy"))
-                        .with_context(|| format!("Unrecognized extension '{ext}'."))
-                }
-            },
-        };
+        let strategy = ExecutionStrategy::detect(executor, interpreters)?;
 
-        match environment {
-            None => Ok(Self {
-                target: target_mover,
-                env: None,
-                strategy,
-            }),
-            Some(x) => {
-                let x = x.as_ref();
-                Ok(Self {
-                    target: target_mover,
-                    env: Some(x.to_path_buf()),
-                    strategy,
-                })
-            }
-        }
+        Ok(Self {
+            target: target_mover,
+            env: environment,
+            strategy,
+            mounts: MountOverrides::default(),
+            timeout: None,
+        })
+    }
+
+    pub fn strategy(&self) -> &ExecutionStrategy {
+        &self.strategy
+    }
+
+    /// Change how the data directories are bind-mounted in containers
+    ///
+    /// The new overrides are added to the ones already set.
+    pub fn add_mount_overrides(&mut self, mounts: MountOverrides) {
+        self.mounts.replace.extend(mounts.replace);
+        self.mounts.read_only.extend(mounts.read_only);
     }
 
-    #[allow(dead_code)]
-    pub fn strategy(&self) -> ExecutionStrategy {
-        self.strategy
+    /// Stop the execution if it lasts more than `timeout`
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
     }
 
     /// Will this executor run in an environment?
     pub fn has_env(&self) -> bool {
         self.env.is_some()
     }
+
+    /// Will the data directories be bind-mounted by Kerblam! in a container?
+    ///
+    /// This is false for local runs, but also for workflow managers that
+    /// handle containers by themselves.
+    pub fn mounts_data_dirs(&self) -> bool {
+        self.env.is_some() && !self.strategy.manages_containers()
+    }
+}
+
+/// Get an existing image ready to be run, and return its name
+///
+/// Docker and Podman pull missing images by themselves when running them,
+/// so they are only checked if `offline`. Apptainer images are pulled to a
+/// file once, and the path to the file is returned.
+fn prepare_image(backend: &ContainerBackend, reference: &str, offline: bool) -> Result<String> {
+    let command: String = backend.clone().into();
+    if !backend.is_docker_like() {
+        let image = apptainer::pull_image(&command, reference, offline)?;
+        return Ok(image.to_string_lossy().to_string());
+    }
+
+    if offline {
+        let available = Command::new(&command)
+            .args(["image", "inspect", reference])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|x| x.success());
+        if !available {
+            bail!(
+                "The image '{}' is not available locally, and cannot be pulled in offline mode. Pull it first with `{} pull {}`.",
+                reference,
+                command,
+                reference
+            )
+        }
+    }
+
+    Ok(reference.to_string())
 }
 
 #[derive(Debug, Clone)]
@@ -438,7 +677,6 @@ impl FileMover {
     pub fn get_from(&self) -> PathBuf {
         self.from.clone()
     }
-    #[allow(dead_code)]
     pub fn get_to(&self) -> PathBuf {
         self.to.clone()
     }
@@ -472,11 +710,24 @@ pub enum CommandResult {
 /// Panics if something really bad happens and the kernel cannot get a handle
 /// on what the child is doing.
 pub fn run_protected_command<F>(cmd_builder: F) -> Result<CommandResult>
+where
+    F: FnOnce() -> Child,
+{
+    run_protected_command_with_timeout(cmd_builder, None)
+}
+
+/// Run a command like `run_protected_command`, but kill it if it lasts
+/// more than `timeout`.
+pub fn run_protected_command_with_timeout<F>(
+    cmd_builder: F,
+    timeout: Option<Duration>,
+) -> Result<CommandResult>
 where
     F: FnOnce() -> Child,
 {
     let mut child = cmd_builder();
     let receiver = KEYBOARD_INTERRUPT_RECEIVER.clone();
+    let start = Instant::now();
 
     loop {
         // Check every 50 ms how the child is faring.
@@ -484,7 +735,7 @@ where
 
         // If we got a kill signal, kill the child, obi-wan kenobi!
         if let Ok(true) = receiver.try_recv() {
-            match child.kill() {
+            match kill_child(&mut child) {
                 Ok(_) => return Ok(CommandResult::Killed),
                 Err(_) => {
                     bail!("Failed to kill child!")
@@ -492,9 +743,49 @@ where
             }
         };
 
+        if timeout.is_some_and(|x| start.elapsed() > x) {
+            eprintln!("\n⏱️  The process took longer than its timeout, stopping it.");
+            match kill_child(&mut child) {
+                Ok(_) => return Ok(CommandResult::Killed),
+                Err(_) => {
+                    bail!("Failed to kill child!")
+                }
+            }
+        }
+
         // Check if the children is done
         if let Some(status) = child.try_wait().expect("Where did the child go?") {
             return Ok(CommandResult::Exited { res: status });
         };
     }
 }
+
+/// Kill a child process
+///
+/// If the child leads its own process group (see `Executor::execute`), the
+/// whole group is killed, so that the processes that it started (e.g. the
+/// recipes run by `make`) are stopped too, and do not outlive the run.
+fn kill_child(child: &mut Child) -> std::io::Result<()> {
+    let pid = child.id() as libc::pid_t;
+    // SAFETY: these calls only query the process group and send a signal
+    if unsafe { libc::getpgid(pid) } != pid {
+        return child.kill();
+    }
+    if unsafe { libc::killpg(pid, libc::SIGKILL) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    child.wait().map(|_| ())
+}
+
+/// Stop a docker (or podman) container by name, if it is still running
+///
+/// The container is removed by the backend afterwards, as it is run with
+/// `--rm`.
+fn stop_container(backend: &str, name: &str) {
+    log::debug!("Stopping container {}", name);
+    let _ = Command::new(backend)
+        .args(["kill", name])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+}
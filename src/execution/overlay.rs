@@ -0,0 +1,386 @@
+use std::collections::HashSet;
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+use super::MountOverrides;
+use crate::journal::{Journal, JournalEntry};
+
+/// A profile applied with symlinks, so that the original files are not moved
+/// out of the input data directory.
+///
+/// For containerized runs, this is a directory that mirrors the input data
+/// directory with symlinks, with the files of the profile swapped in, that is
+/// bind-mounted in place of the input directory (see `Overlay::build`).
+/// For local runs, only the profiled paths in the input directory are set
+/// aside and replaced by symlinks (see `Overlay::swap_locally`).
+#[derive(Debug)]
+pub struct Overlay {
+    /// The input data directory that is being overlaid
+    input_dir: PathBuf,
+    /// Where the overlay lives, if one was built
+    overlay_dir: Option<PathBuf>,
+    /// The `(path, stash)` pairs of the paths that were set aside locally
+    stashed: Vec<(PathBuf, PathBuf)>,
+    /// The symlinks made in the input directory
+    links: Vec<PathBuf>,
+}
+
+/// The path where a file or directory is set aside during a local run
+///
+/// This is next to the path itself, so the rename never crosses filesystems.
+pub fn stash_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap().to_string_lossy();
+    path.with_file_name(format!(".{}.kerblam_original", name))
+}
+
+impl Overlay {
+    /// Build an overlay of an input directory, for containerized runs.
+    ///
+    /// The `swaps` are `(original, target)` pairs of paths relative to the
+    /// input dir: in the overlay, the original points to the target, and
+    /// the target is hidden. If the target is None, the original is hidden.
+    ///
+    /// The symlinks point to the input dir, that is mounted at the same
+    /// place in the container (see `Overlay::mount_overrides`).
+    /// The creation of the overlay is recorded in the `journal`.
+    pub fn build(
+        overlay_dir: PathBuf,
+        input_dir: PathBuf,
+        swaps: &[(PathBuf, Option<PathBuf>)],
+        journal: &Journal,
+    ) -> Result<Self> {
+        if overlay_dir.exists() {
+            bail!(
+                "The overlay directory {:?} already exists! Was a previous run interrupted? Try `kerblam recover`.",
+                overlay_dir
+            )
+        }
+        journal.record(JournalEntry::Overlay {
+            path: overlay_dir.clone(),
+        })?;
+
+        let originals: HashSet<&Path> = swaps.iter().map(|(x, _)| x.as_path()).collect();
+        let targets: HashSet<&Path> = swaps.iter().filter_map(|(_, x)| x.as_deref()).collect();
+        let special: Vec<&Path> = originals.union(&targets).copied().collect();
+
+        let linked = link_dir(
+            &input_dir,
+            &overlay_dir,
+            &input_dir,
+            Path::new(""),
+            &originals,
+            &targets,
+            &special,
+        )
+        .and_then(|_| link_swaps(&overlay_dir, &input_dir, swaps));
+        if let Err(e) = linked {
+            let _ = fs::remove_dir_all(&overlay_dir);
+            return Err(e);
+        }
+
+        Ok(Self {
+            input_dir,
+            overlay_dir: Some(overlay_dir),
+            stashed: vec![],
+            links: vec![],
+        })
+    }
+
+    /// Swap the profiled paths for symlinks in the input directory, for
+    /// local runs.
+    ///
+    /// The `swaps` are like in `Overlay::build`. The originals and targets
+    /// are renamed in place (see `stash_path`), so they are hidden, and each
+    /// original is replaced by a symlink to its target. The input directory
+    /// itself, and all other files in it, are not touched.
+    /// All changes are recorded in the `journal` before being made.
+    pub fn swap_locally(
+        input_dir: PathBuf,
+        swaps: &[(PathBuf, Option<PathBuf>)],
+        journal: &Journal,
+    ) -> Result<Self> {
+        let mut overlay = Self {
+            input_dir,
+            overlay_dir: None,
+            stashed: vec![],
+            links: vec![],
+        };
+        if let Err(e) = overlay.swap(swaps, journal) {
+            let _ = overlay.teardown();
+            return Err(e);
+        }
+
+        Ok(overlay)
+    }
+
+    fn swap(&mut self, swaps: &[(PathBuf, Option<PathBuf>)], journal: &Journal) -> Result<()> {
+        let mut hidden: Vec<PathBuf> = vec![];
+        for (original, target) in swaps {
+            for path in [Some(original), target.as_ref()].into_iter().flatten() {
+                let path = self.input_dir.join(path);
+                if !hidden.contains(&path) && path.symlink_metadata().is_ok() {
+                    hidden.push(path);
+                }
+            }
+        }
+        if let Some(stash) = hidden.iter().map(|x| stash_path(x)).find(|x| x.exists()) {
+            bail!(
+                "{:?} already exists! Was a previous run interrupted? Try `kerblam recover`.",
+                stash
+            )
+        }
+
+        for path in hidden {
+            let stash = stash_path(&path);
+            log::debug!("Setting {:?} aside to {:?}", path, stash);
+            journal.record(JournalEntry::Rename {
+                from: path.clone(),
+                to: stash.clone(),
+            })?;
+            fs::rename(&path, &stash)?;
+            self.stashed.push((path, stash));
+        }
+
+        for (original, target) in swaps {
+            if let Some(target) = target {
+                let link = self.input_dir.join(original);
+                if let Some(parent) = link.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                let target = stash_path(&self.input_dir.join(target));
+                log::debug!("Linking {:?} to {:?}", link, target);
+                journal.record(JournalEntry::Symlink { link: link.clone() })?;
+                symlink(target, &link)?;
+                self.links.push(link);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The bind mounts needed to use the overlay in a container.
+    ///
+    /// The overlay replaces the input directory, and the real input directory
+    /// is mounted read-only where the symlinks expect it.
+    pub fn mount_overrides(&self) -> MountOverrides {
+        let mut overrides = MountOverrides::default();
+        if let Some(overlay_dir) = &self.overlay_dir {
+            overrides
+                .replace
+                .insert(self.input_dir.clone(), overlay_dir.clone());
+            overrides.read_only.push(self.input_dir.clone());
+        }
+
+        overrides
+    }
+
+    /// Remove the overlay, or put the swapped paths back.
+    ///
+    /// Files that were created in the overlay during the run are moved to
+    /// the real input directory, so nothing is lost.
+    pub fn teardown(self) -> Result<()> {
+        for link in self.links.iter().rev() {
+            if !link.is_symlink() {
+                bail!(
+                    "{:?} was replaced during the run. Move it out of the way first.",
+                    link
+                )
+            }
+            fs::remove_file(link)
+                .with_context(|| format!("Failed to remove the symlink at {:?}", link))?;
+        }
+        for (path, stash) in self.stashed.iter().rev() {
+            fs::rename(stash, path).with_context(|| format!("Failed to move {:?} back", stash))?;
+        }
+
+        let Some(overlay_dir) = self.overlay_dir else {
+            return Ok(());
+        };
+        let all_rescued = rescue_new_files(&overlay_dir, &self.input_dir, Path::new(""))?;
+        if !all_rescued {
+            bail!(
+                "Some files could not be moved out of {:?}. Move them manually, then delete it.",
+                overlay_dir
+            )
+        }
+        // This does not follow symlinks, so the real files are safe
+        fs::remove_dir_all(&overlay_dir)?;
+
+        Ok(())
+    }
+}
+
+/// Recursively mirror a directory with symlinks
+///
+/// `relative` is the path of the directory being linked, relative to the
+/// input dir. Originals and targets of the swaps are skipped, and
+/// directories that contain them are recreated instead of being linked.
+fn link_dir(
+    input_dir: &Path,
+    overlay_dir: &Path,
+    real_dir: &Path,
+    relative: &Path,
+    originals: &HashSet<&Path>,
+    targets: &HashSet<&Path>,
+    special: &[&Path],
+) -> Result<()> {
+    fs::create_dir_all(overlay_dir.join(relative))?;
+
+    for entry in fs::read_dir(input_dir.join(relative))? {
+        let entry = entry?;
+        let path = relative.join(entry.file_name());
+
+        if originals.contains(path.as_path()) || targets.contains(path.as_path()) {
+            continue;
+        }
+
+        let contains_special = special.iter().any(|x| x.starts_with(&path));
+        if entry.file_type()?.is_dir() && contains_special {
+            link_dir(
+                input_dir,
+                overlay_dir,
+                real_dir,
+                &path,
+                originals,
+                targets,
+                special,
+            )?;
+        } else {
+            symlink(real_dir.join(&path), overlay_dir.join(&path))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Link the originals of the swaps to their targets, in the overlay
+fn link_swaps(
+    overlay_dir: &Path,
+    real_dir: &Path,
+    swaps: &[(PathBuf, Option<PathBuf>)],
+) -> Result<()> {
+    for (original, target) in swaps {
+        if let Some(target) = target {
+            let link = overlay_dir.join(original);
+            if let Some(parent) = link.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            log::debug!("Linking {:?} to {:?}", link, real_dir.join(target));
+            symlink(real_dir.join(target), &link)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Move files that are not symlinks from the overlay to the real directory
+///
+/// Returns false if some files could not be moved.
+fn rescue_new_files(overlay_dir: &Path, real_dir: &Path, relative: &Path) -> Result<bool> {
+    let mut all_rescued = true;
+    for entry in fs::read_dir(overlay_dir.join(relative))? {
+        let entry = entry?;
+        let path = relative.join(entry.file_name());
+        let file_type = entry.file_type()?;
+
+        if file_type.is_symlink() {
+            continue;
+        }
+        if file_type.is_dir() && real_dir.join(&path).is_dir() {
+            all_rescued &= rescue_new_files(overlay_dir, real_dir, &path)?;
+            continue;
+        }
+        if real_dir.join(&path).exists() {
+            eprintln!(
+                "⚠️  {:?} was written during the run, but it already exists in the input directory.",
+                path
+            );
+            all_rescued = false;
+            continue;
+        }
+
+        eprintln!(
+            "⚠️  {:?} was written to the input directory during the run. Keeping it.",
+            path
+        );
+        fs::rename(overlay_dir.join(&path), real_dir.join(&path))?;
+    }
+
+    Ok(all_rescued)
+}
+
+#[test]
+fn test_overlay() {
+    let root = tempfile::tempdir().unwrap();
+    let input = root.path().join("in");
+    fs::create_dir_all(input.join("samples")).unwrap();
+    for file in [
+        "a.txt",
+        "test_a.txt",
+        "hidden.txt",
+        "samples/x.txt",
+        "samples/test_x.txt",
+    ] {
+        fs::write(input.join(file), file).unwrap();
+    }
+
+    let swaps = vec![
+        (PathBuf::from("a.txt"), Some(PathBuf::from("test_a.txt"))),
+        (PathBuf::from("hidden.txt"), None),
+        (
+            PathBuf::from("samples/x.txt"),
+            Some(PathBuf::from("samples/test_x.txt")),
+        ),
+    ];
+    let journal = Journal::at(root.path().join("journal"));
+
+    // In containers, the overlay shows the profile files in place of the originals
+    let overlay_dir = root.path().join("overlay");
+    let overlay = Overlay::build(overlay_dir.clone(), input.clone(), &swaps, &journal).unwrap();
+    assert_eq!(
+        fs::read_link(overlay_dir.join("a.txt")).unwrap(),
+        input.join("test_a.txt")
+    );
+    assert!(overlay_dir
+        .join("samples/test_x.txt")
+        .symlink_metadata()
+        .is_err());
+    assert!(overlay_dir.join("hidden.txt").symlink_metadata().is_err());
+    fs::write(overlay_dir.join("new.txt"), "new").unwrap();
+    overlay.teardown().unwrap();
+    assert_eq!(fs::read_to_string(input.join("new.txt")).unwrap(), "new");
+    assert!(!overlay_dir.exists());
+    journal.clear().unwrap();
+
+    // Locally, the input dir shows them directly, and is never moved
+    let overlay = Overlay::swap_locally(input.clone(), &swaps, &journal).unwrap();
+    assert_eq!(journal.entries().unwrap().len(), 7);
+    assert!(!input.is_symlink());
+    assert_eq!(
+        fs::read_to_string(input.join("a.txt")).unwrap(),
+        "test_a.txt"
+    );
+    assert_eq!(
+        fs::read_to_string(input.join("samples/x.txt")).unwrap(),
+        "samples/test_x.txt"
+    );
+    assert!(!input.join("test_a.txt").exists());
+    assert!(!input.join("hidden.txt").exists());
+
+    overlay.teardown().unwrap();
+
+    // Everything is back as it was
+    assert_eq!(fs::read_to_string(input.join("a.txt")).unwrap(), "a.txt");
+    assert_eq!(
+        fs::read_to_string(input.join("test_a.txt")).unwrap(),
+        "test_a.txt"
+    );
+    assert_eq!(
+        fs::read_to_string(input.join("hidden.txt")).unwrap(),
+        "hidden.txt"
+    );
+    assert!(!stash_path(&input.join("a.txt")).exists());
+}
@@ -0,0 +1,208 @@
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+
+use super::{stash_path, MountOverrides};
+use crate::journal::{Journal, JournalEntry};
+
+/// A data directory (e.g. the output directory) sent somewhere else for the
+/// duration of a run.
+///
+/// For containerized runs, the new directory is bind-mounted in place of the
+/// data directory. For local runs, the data directory is temporarily renamed
+/// and replaced by a symlink to the new directory.
+///
+/// Other data directories inside the redirected one (like `data/in` in the
+/// default `data` intermediate directory) are not redirected with it.
+#[derive(Debug)]
+pub struct Redirect {
+    /// The data directory that is redirected
+    data_dir: PathBuf,
+    /// Where the data directory is redirected to
+    target: PathBuf,
+    /// The other data directories inside `data_dir`
+    contained: Vec<PathBuf>,
+    /// Where the real data directory was moved to, if it was
+    stash_dir: Option<PathBuf>,
+    /// The symlinks to the `contained` dirs made in the target
+    links: Vec<PathBuf>,
+}
+
+impl Redirect {
+    /// Prepare a redirect, creating the target directory if needed
+    pub fn new(data_dir: PathBuf, target: PathBuf, contained: Vec<PathBuf>) -> Result<Self> {
+        fs::create_dir_all(&data_dir)?;
+        fs::create_dir_all(&target)
+            .with_context(|| format!("Cannot create the directory {:?}", target))?;
+
+        Ok(Self {
+            data_dir,
+            target,
+            contained,
+            stash_dir: None,
+            links: vec![],
+        })
+    }
+
+    /// Put a symlink to the target in place of the data directory.
+    ///
+    /// If the target is inside the data directory, the symlink points to
+    /// where it ends up after the data directory is stashed away.
+    /// The contained data directories are kept in place by symlinks to them
+    /// in the target.
+    /// All changes are recorded in the `journal` before being made.
+    pub fn enable_locally(&mut self, journal: &Journal) -> Result<()> {
+        let stash = stash_path(&self.data_dir);
+        if stash.exists() {
+            bail!(
+                "{:?} already exists! Was a previous run interrupted? Try `kerblam recover`.",
+                stash
+            )
+        }
+        let real_target = match self.target.strip_prefix(&self.data_dir) {
+            Ok(relative) => stash.join(relative),
+            Err(_) => self.target.clone(),
+        };
+
+        log::debug!("Redirecting {:?} to {:?}", self.data_dir, real_target);
+        journal.record(JournalEntry::Rename {
+            from: self.data_dir.clone(),
+            to: stash.clone(),
+        })?;
+        fs::rename(&self.data_dir, &stash)?;
+        journal.record(JournalEntry::Symlink {
+            link: self.data_dir.clone(),
+        })?;
+        if let Err(e) = symlink(&real_target, &self.data_dir) {
+            fs::rename(&stash, &self.data_dir)?;
+            return Err(e.into());
+        }
+        self.stash_dir = Some(stash.clone());
+
+        for contained in &self.contained {
+            let relative = contained.strip_prefix(&self.data_dir)?;
+            let link = real_target.join(relative);
+            // Container runs leave empty mount points behind
+            if link.is_dir() && !link.is_symlink() && fs::read_dir(&link)?.next().is_none() {
+                fs::remove_dir(&link)?;
+            }
+            if link.symlink_metadata().is_ok() {
+                bail!(
+                    "Cannot keep {:?} in place while redirecting {:?}, as {:?} already exists.",
+                    contained,
+                    self.data_dir,
+                    link
+                )
+            }
+            if let Some(parent) = link.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            journal.record(JournalEntry::Symlink { link: link.clone() })?;
+            symlink(stash.join(relative), &link)?;
+            self.links.push(link);
+        }
+
+        Ok(())
+    }
+
+    /// The bind mounts needed to use the redirect in a container
+    pub fn mount_overrides(&self) -> MountOverrides {
+        let mut overrides = MountOverrides::default();
+        overrides
+            .replace
+            .insert(self.data_dir.clone(), self.target.clone());
+
+        overrides
+    }
+
+    /// Put the data directory back, if it was moved
+    pub fn teardown(self) -> Result<()> {
+        for link in &self.links {
+            fs::remove_file(link)
+                .with_context(|| format!("Failed to remove the symlink at {:?}", link))?;
+        }
+        if let Some(stash) = &self.stash_dir {
+            fs::remove_file(&self.data_dir)
+                .with_context(|| format!("Failed to remove the symlink at {:?}", self.data_dir))?;
+            fs::rename(stash, &self.data_dir)
+                .with_context(|| format!("Failed to move {:?} back", stash))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_nested_redirect() {
+    let root = tempfile::tempdir().unwrap();
+    let output = root.path().join("out");
+    fs::create_dir_all(&output).unwrap();
+    fs::write(output.join("real.txt"), "real").unwrap();
+
+    let journal = Journal::at(root.path().join("journal"));
+    let mut redirect = Redirect::new(output.clone(), output.join("test"), vec![]).unwrap();
+    redirect.enable_locally(&journal).unwrap();
+
+    // The workflow writes to the output dir, but ends up in the redirect
+    assert!(!output.join("real.txt").exists());
+    fs::write(output.join("result.txt"), "test").unwrap();
+
+    redirect.teardown().unwrap();
+
+    assert!(!output.is_symlink());
+    assert_eq!(fs::read_to_string(output.join("real.txt")).unwrap(), "real");
+    assert_eq!(
+        fs::read_to_string(output.join("test/result.txt")).unwrap(),
+        "test"
+    );
+    assert!(!output.join("result.txt").exists());
+}
+
+#[test]
+fn test_redirect_default_layout() {
+    let root = tempfile::tempdir().unwrap();
+    let data = root.path().join("data");
+    let (input, output) = (data.join("in"), data.join("out"));
+    fs::create_dir_all(&input).unwrap();
+    fs::create_dir_all(&output).unwrap();
+    fs::write(input.join("input.txt"), "input").unwrap();
+    fs::write(data.join("tmp.txt"), "tmp").unwrap();
+
+    // Redirect the intermediate dir, then the output dir inside it
+    let journal = Journal::at(root.path().join("journal"));
+    let mut intermediate = Redirect::new(
+        data.clone(),
+        data.join("test"),
+        vec![input.clone(), output.clone()],
+    )
+    .unwrap();
+    intermediate.enable_locally(&journal).unwrap();
+    let mut out = Redirect::new(output.clone(), output.join("test"), vec![]).unwrap();
+    out.enable_locally(&journal).unwrap();
+
+    // The input is still there, but the intermediate files are not
+    assert_eq!(
+        fs::read_to_string(input.join("input.txt")).unwrap(),
+        "input"
+    );
+    assert!(!data.join("tmp.txt").exists());
+    fs::write(data.join("new_tmp.txt"), "new").unwrap();
+    fs::write(output.join("result.txt"), "test").unwrap();
+
+    out.teardown().unwrap();
+    intermediate.teardown().unwrap();
+
+    assert!(!data.is_symlink() && !output.is_symlink());
+    assert_eq!(fs::read_to_string(data.join("tmp.txt")).unwrap(), "tmp");
+    assert_eq!(
+        fs::read_to_string(data.join("test/new_tmp.txt")).unwrap(),
+        "new"
+    );
+    assert_eq!(
+        fs::read_to_string(output.join("test/result.txt")).unwrap(),
+        "test"
+    );
+    assert!(!data.join("test/in").exists() && !data.join("test/out").exists());
+}
@@ -1,13 +1,185 @@
-#[derive(Debug, Clone)]
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+/// The interpreters used for script extensions that are not configured
+/// in `[execution.interpreters]`, nor have a shebang.
+pub const DEFAULT_INTERPRETERS: [(&str, &str); 2] = [("py", "python3"), ("R", "Rscript")];
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum ExecutionStrategy {
     Make,
     Shell,
+    Snakemake,
+    Nextflow,
+    /// Run the file as a script with this command (and its arguments)
+    Interpreter(Vec<String>),
 }
 
-impl Copy for ExecutionStrategy {}
-
 impl ExecutionStrategy {
-    pub fn to_command_vec(self) {
+    pub fn to_command_vec(&self) {
         todo!();
     }
+
+    /// Get the strategy used to run pipes with this file extension, if any
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "makefile" => Some(Self::Make),
+            "sh" => Some(Self::Shell),
+            "smk" | "snakefile" => Some(Self::Snakemake),
+            "nf" => Some(Self::Nextflow),
+            _ => None,
+        }
+    }
+
+    /// Find out how to run a pipe file
+    ///
+    /// In order, this uses:
+    /// - The strategies with special handling (see `from_extension`);
+    /// - The interpreter set for the extension in `interpreters`;
+    /// - The shebang of the file, if it has one, but only for files with no
+    ///   extension or with one in the `DEFAULT_INTERPRETERS`;
+    /// - The `DEFAULT_INTERPRETERS`.
+    ///
+    /// So that other files in the pipes dir (e.g. helper scripts like
+    /// `utils.pl`) are not taken for pipes just because of their shebang.
+    pub fn detect(pipe: &Path, interpreters: &HashMap<String, String>) -> Result<Self> {
+        let extension = pipe.extension().map(|x| x.to_string_lossy().to_string());
+
+        if let Some(strategy) = extension.as_deref().and_then(Self::from_extension) {
+            return Ok(strategy);
+        }
+        if let Some((ext, command)) = extension
+            .as_ref()
+            .and_then(|x| interpreters.get(x).map(|command| (x, command)))
+        {
+            let command = split_command(command);
+            if command.is_empty() {
+                bail!("The interpreter for '{ext}' in [execution.interpreters] is empty");
+            }
+            return Ok(Self::Interpreter(command));
+        }
+        let default = DEFAULT_INTERPRETERS
+            .iter()
+            .find(|(x, _)| extension.as_deref() == Some(x));
+        if extension.is_none() || default.is_some() {
+            if let Some(command) = read_shebang(pipe)? {
+                return Ok(Self::Interpreter(split_command(&command)));
+            }
+        }
+        if let Some((_, command)) = default {
+            return Ok(Self::Interpreter(split_command(command)));
+        }
+
+        let err = anyhow!("Cannot determine execution strategy");
+        match extension {
+            Some(ext) => Err(err).with_context(|| {
+                format!(
+                    "Unrecognized extension '{ext}'. Set an interpreter for it in [execution.interpreters]."
+                )
+            }),
+            None => Err(err).with_context(|| "Specified executor has no extension nor shebang"),
+        }
+    }
+
+    /// Would this file only be run because of its extension?
+    ///
+    /// That is, its extension is one of the `DEFAULT_INTERPRETERS`, and it
+    /// is not set in `interpreters`, and the file has no shebang.
+    pub fn only_by_default(pipe: &Path, interpreters: &HashMap<String, String>) -> bool {
+        let extension = pipe.extension().map(|x| x.to_string_lossy().to_string());
+        let is_default = DEFAULT_INTERPRETERS
+            .iter()
+            .any(|(x, _)| extension.as_deref() == Some(x));
+
+        is_default
+            && !extension.is_some_and(|x| interpreters.contains_key(&x))
+            && read_shebang(pipe).ok().flatten().is_none()
+    }
+
+    /// Does the workflow manager run its own containers?
+    ///
+    /// If so, Kerblam! only builds the container image and hands it to the
+    /// workflow manager, that is run locally.
+    pub fn manages_containers(&self) -> bool {
+        matches!(self, Self::Nextflow)
+    }
+}
+
+/// Split an interpreter command (e.g. "python3 -u") in its arguments
+fn split_command(command: &str) -> Vec<String> {
+    command.split_whitespace().map(|x| x.to_string()).collect()
+}
+
+/// Read the interpreter from the shebang line of a file, if it has one
+fn read_shebang(path: &Path) -> Result<Option<String>> {
+    let mut first_line = String::new();
+    // Binary files and the like just have no shebang
+    if BufReader::new(File::open(path)?)
+        .read_line(&mut first_line)
+        .is_err()
+    {
+        return Ok(None);
+    }
+
+    Ok(first_line
+        .strip_prefix("#!")
+        .map(|x| x.trim().to_string())
+        .filter(|x| !x.is_empty()))
+}
+
+#[test]
+fn test_detect_strategy() {
+    let root = tempfile::tempdir().unwrap();
+    let write = |name: &str, content: &str| {
+        let path = root.path().join(name);
+        std::fs::write(&path, content).unwrap();
+        path
+    };
+    let interpreters: HashMap<String, String> =
+        HashMap::from([("jl".to_string(), "julia --threads 4".to_string())]);
+    let detect =
+        |path: std::path::PathBuf| ExecutionStrategy::detect(&path, &interpreters).unwrap();
+    let interpreter =
+        |x: &[&str]| ExecutionStrategy::Interpreter(x.iter().map(|x| x.to_string()).collect());
+
+    // The shebang is ignored for the special strategies
+    assert_eq!(
+        detect(write("a.sh", "#!/usr/bin/env zsh\n")),
+        ExecutionStrategy::Shell
+    );
+    assert_eq!(
+        detect(write("b.jl", "#!/bin/julia\n")),
+        interpreter(&["julia", "--threads", "4"])
+    );
+    assert_eq!(
+        detect(write("c.py", "#!/usr/bin/env python2\n")),
+        interpreter(&["/usr/bin/env", "python2"])
+    );
+    assert_eq!(
+        detect(write("d.py", "print(1)\n")),
+        interpreter(&["python3"])
+    );
+    assert_eq!(
+        detect(write("e", "#!/usr/bin/perl -w\n")),
+        interpreter(&["/usr/bin/perl", "-w"])
+    );
+
+    assert!(ExecutionStrategy::only_by_default(
+        &write("h.py", "import os\n"),
+        &interpreters
+    ));
+    assert!(!ExecutionStrategy::only_by_default(
+        &write("i.py", "#!/usr/bin/env python3\n"),
+        &interpreters
+    ));
+
+    assert!(ExecutionStrategy::detect(&write("f.txt", "hello\n"), &interpreters).is_err());
+    assert!(ExecutionStrategy::detect(&write("f.pl", "#!/usr/bin/perl\n"), &interpreters).is_err());
+
+    let blank = HashMap::from([("py".to_string(), "  ".to_string())]);
+    assert!(ExecutionStrategy::detect(&write("g.py", "print(1)\n"), &blank).is_err());
 }
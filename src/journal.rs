@@ -0,0 +1,196 @@
+use std::env::current_dir;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+/// A change to the filesystem made while applying a profile.
+///
+/// Each entry is written to the journal *before* the change is made, so
+/// that it can be undone even if Kerblam! is killed halfway through.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum JournalEntry {
+    /// A file or directory was moved from `from` to `to`
+    Rename { from: PathBuf, to: PathBuf },
+    /// A symlink was created at `link`
+    Symlink { link: PathBuf },
+    /// A directory made only of symlinks (and directories) was created
+    Overlay { path: PathBuf },
+}
+
+impl JournalEntry {
+    /// Undo this change, if it was actually made and not undone already.
+    pub fn undo(&self) -> Result<()> {
+        match self {
+            Self::Rename { from, to } => {
+                if to.symlink_metadata().is_ok() && from.symlink_metadata().is_err() {
+                    eprintln!("  Moving {:?} back to {:?}", to, from);
+                    fs::rename(to, from)?;
+                }
+            }
+            Self::Symlink { link } => {
+                if link.is_symlink() {
+                    eprintln!("  Removing the symlink {:?}", link);
+                    fs::remove_file(link)?;
+                }
+            }
+            Self::Overlay { path } => {
+                if path.exists() {
+                    let written: Vec<PathBuf> = WalkDir::new(path)
+                        .into_iter()
+                        .filter_map(|x| x.ok())
+                        .filter(|x| x.file_type().is_file())
+                        .map(|x| x.into_path())
+                        .collect();
+                    if !written.is_empty() {
+                        bail!(
+                            "The overlay {:?} contains files that were written during the interrupted run: {:?}. Move them out, then try again.",
+                            path,
+                            written
+                        )
+                    }
+                    eprintln!("  Removing the overlay {:?}", path);
+                    // This does not follow symlinks, so the real files are safe
+                    fs::remove_dir_all(path)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// An on-disk, append-only log of the changes made while applying a profile.
+///
+/// The journal is stored as one JSON object per line.
+#[derive(Debug, Clone)]
+pub struct Journal {
+    path: PathBuf,
+}
+
+impl Journal {
+    /// The journal of the project in the current directory
+    pub fn current() -> Self {
+        Self::at(current_dir().unwrap().join(".kerblam/journal"))
+    }
+
+    pub fn at(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Write an entry to the journal, making sure that it reaches the disk
+    pub fn record(&self, entry: JournalEntry) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Cannot open the journal at {:?}", self.path))?;
+        writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        file.sync_all()?;
+
+        Ok(())
+    }
+
+    /// Read all the entries of the journal, oldest first
+    ///
+    /// A truncated last line (e.g. if we were killed while writing it) is
+    /// ignored, as the change it describes was never made.
+    pub fn entries(&self) -> Result<Vec<JournalEntry>> {
+        if !self.path.exists() {
+            return Ok(vec![]);
+        }
+        let content = fs::read_to_string(&self.path)?;
+        let lines: Vec<&str> = content.lines().filter(|x| !x.trim().is_empty()).collect();
+
+        let mut entries = vec![];
+        for (i, line) in lines.iter().enumerate() {
+            match serde_json::from_str(line) {
+                Ok(entry) => entries.push(entry),
+                Err(_) if i == lines.len() - 1 => {
+                    log::warn!("Ignoring truncated journal entry {:?}", line)
+                }
+                Err(e) => {
+                    return Err(e).with_context(|| {
+                        format!("Corrupted journal entry in {:?}: {:?}", self.path, line)
+                    })
+                }
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Are there changes in the journal that were not cleaned up?
+    pub fn is_empty(&self) -> bool {
+        self.entries().map(|x| x.is_empty()).unwrap_or(false)
+    }
+
+    /// Forget all the entries of the journal
+    pub fn clear(&self) -> Result<()> {
+        if self.path.exists() {
+            fs::remove_file(&self.path)?;
+        }
+        Ok(())
+    }
+
+    /// Undo all the changes in the journal, newest first, then clear it.
+    ///
+    /// Stops at the first change that cannot be undone, leaving the journal
+    /// in place so that it can be tried again.
+    pub fn replay(&self) -> Result<()> {
+        for entry in self.entries()?.iter().rev() {
+            entry
+                .undo()
+                .with_context(|| format!("Failed to undo {:?}", entry))?;
+        }
+
+        self.clear()
+    }
+}
+
+#[test]
+fn test_journal_replay() {
+    let root = tempfile::tempdir().unwrap();
+    let journal = Journal::at(root.path().join(".kerblam/journal"));
+    let original = root.path().join("input.txt");
+    let scratch = root.path().join("scratch.txt");
+    let profile = root.path().join("test_input.txt");
+    fs::write(&original, "original").unwrap();
+    fs::write(&profile, "profile").unwrap();
+
+    // Simulate a profile swap that was interrupted halfway through
+    journal
+        .record(JournalEntry::Rename {
+            from: original.clone(),
+            to: scratch.clone(),
+        })
+        .unwrap();
+    fs::rename(&original, &scratch).unwrap();
+    journal
+        .record(JournalEntry::Rename {
+            from: profile.clone(),
+            to: original.clone(),
+        })
+        .unwrap();
+    fs::rename(&profile, &original).unwrap();
+    journal
+        .record(JournalEntry::Symlink {
+            link: root.path().join("never_created"),
+        })
+        .unwrap();
+
+    assert!(!journal.is_empty());
+    journal.replay().unwrap();
+
+    assert_eq!(fs::read_to_string(&original).unwrap(), "original");
+    assert_eq!(fs::read_to_string(&profile).unwrap(), "profile");
+    assert!(!scratch.exists());
+    assert!(journal.is_empty());
+}
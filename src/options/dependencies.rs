@@ -0,0 +1,102 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{bail, Result};
+
+/// Find the order to run a pipe and all the pipes that it depends on
+///
+/// `dependencies` maps the name of each pipe to the names of the pipes that
+/// it depends on. Every pipe is listed once, after all of its dependencies,
+/// and the `target` is last.
+///
+/// Fails if a pipe depends on a pipe that does not exist, or if the
+/// dependencies have a cycle.
+pub fn dependency_order(
+    target: &str,
+    dependencies: &HashMap<String, Vec<String>>,
+) -> Result<Vec<String>> {
+    let mut order: Vec<String> = vec![];
+    let mut done: HashSet<String> = HashSet::new();
+    visit(target, dependencies, &mut vec![], &mut done, &mut order)?;
+
+    Ok(order)
+}
+
+/// Depth-first visit of the dependencies of `pipe`
+///
+/// `path` is the chain of pipes that led to this one, to detect cycles.
+fn visit(
+    pipe: &str,
+    dependencies: &HashMap<String, Vec<String>>,
+    path: &mut Vec<String>,
+    done: &mut HashSet<String>,
+    order: &mut Vec<String>,
+) -> Result<()> {
+    if done.contains(pipe) {
+        return Ok(());
+    }
+    if let Some(start) = path.iter().position(|x| x == pipe) {
+        let mut cycle = path[start..].to_vec();
+        cycle.push(pipe.to_string());
+        bail!("The pipes depend on each other: {}", cycle.join(" -> "))
+    }
+    let upstream = match dependencies.get(pipe) {
+        Some(x) => x,
+        None => match path.last() {
+            Some(parent) => bail!(
+                "The pipe '{}' depends on '{}', but there is no such pipe",
+                parent,
+                pipe
+            ),
+            None => bail!("There is no pipe named '{}'", pipe),
+        },
+    };
+
+    path.push(pipe.to_string());
+    for dependency in upstream {
+        visit(dependency, dependencies, path, done, order)?;
+    }
+    path.pop();
+
+    done.insert(pipe.to_string());
+    order.push(pipe.to_string());
+
+    Ok(())
+}
+
+#[test]
+fn test_dependency_order() {
+    let make = |pairs: &[(&str, &[&str])]| -> HashMap<String, Vec<String>> {
+        pairs
+            .iter()
+            .map(|(name, deps)| {
+                (
+                    name.to_string(),
+                    deps.iter().map(|x| x.to_string()).collect(),
+                )
+            })
+            .collect()
+    };
+
+    let dependencies = make(&[
+        ("preprocess", &[]),
+        ("analyze", &["preprocess"]),
+        ("plots", &["preprocess"]),
+        ("report", &["analyze", "plots"]),
+        ("other", &[]),
+    ]);
+    assert_eq!(
+        dependency_order("report", &dependencies).unwrap(),
+        vec!["preprocess", "analyze", "plots", "report"]
+    );
+    assert_eq!(
+        dependency_order("other", &dependencies).unwrap(),
+        vec!["other"]
+    );
+
+    let cycle = make(&[("a", &["b"]), ("b", &["c"]), ("c", &["a"])]);
+    let error = dependency_order("a", &cycle).unwrap_err().to_string();
+    assert!(error.contains("a -> b -> c -> a"));
+
+    let missing = make(&[("a", &["nope"])]);
+    assert!(dependency_order("a", &missing).is_err());
+}
@@ -0,0 +1,100 @@
+use anyhow::{anyhow, Result};
+use toml_edit::{DocumentMut, InlineTable, Item, Key, Table};
+
+/// Set a value in a TOML document given its dotted key.
+///
+/// Missing tables are created (as implicit tables, so that only the deepest
+/// one gets a header). If the value already exists, its comments are kept.
+pub fn set_dotted(
+    document: &mut DocumentMut,
+    dotted: &str,
+    mut value: toml_edit::Value,
+) -> Result<()> {
+    let keys = Key::parse(dotted).map_err(|e| anyhow!("Invalid key {:?}: {}", dotted, e))?;
+    let (last, parents) = keys
+        .split_last()
+        .ok_or(anyhow!("Invalid empty key {:?}", dotted))?;
+
+    let mut current = document.as_item_mut();
+    for (i, key) in parents.iter().enumerate() {
+        let is_inline = current.is_inline_table();
+        let table = current.as_table_like_mut().ok_or(anyhow!(
+            "Cannot set `{}`: `{}` is not a table",
+            dotted,
+            keys[..i]
+                .iter()
+                .map(|x| x.get())
+                .collect::<Vec<_>>()
+                .join(".")
+        ))?;
+        if table.get(key.get()).is_none() {
+            let new = if is_inline {
+                Item::Value(InlineTable::new().into())
+            } else {
+                let mut new = Table::new();
+                new.set_implicit(true);
+                Item::Table(new)
+            };
+            table.insert(key.get(), new);
+        }
+        current = table.get_mut(key.get()).unwrap();
+    }
+
+    let table = current.as_table_like_mut().ok_or(anyhow!(
+        "Cannot set `{}`: `{}` is not a table",
+        dotted,
+        parents
+            .iter()
+            .map(|x| x.get())
+            .collect::<Vec<_>>()
+            .join(".")
+    ))?;
+    value.decor_mut().clear();
+    match table.get_mut(last.get()) {
+        Some(Item::Value(old)) => {
+            *value.decor_mut() = old.decor().clone();
+            *old = value;
+        }
+        _ => {
+            table.insert(last.get(), Item::Value(value));
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_set_dotted_preserves_formatting() {
+    let mut document: DocumentMut = r#"# My project
+[data.paths]
+input = "data/in" # the raw data
+
+[data.profiles]
+test = { "a.txt" = "test_a.txt" }
+"#
+    .parse()
+    .unwrap();
+
+    set_dotted(&mut document, "data.paths.input", "/scratch/in".into()).unwrap();
+    set_dotted(&mut document, "execution.backend", "podman".into()).unwrap();
+    set_dotted(
+        &mut document,
+        "data.profiles.test.\"b.txt\"",
+        "test_b.txt".into(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        document.to_string(),
+        r#"# My project
+[data.paths]
+input = "/scratch/in" # the raw data
+
+[data.profiles]
+test = { "a.txt" = "test_a.txt" , "b.txt" = "test_b.txt" }
+
+[execution]
+backend = "podman"
+"#
+    );
+}
@@ -0,0 +1,64 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+/// Check that a container image reference, like `ghcr.io/org/tool:1.4`, is
+/// valid
+///
+/// The reference can be pinned to a digest, like
+/// `ghcr.io/org/tool:1.4@sha256:<digest>`, in which case the digest must
+/// be a valid sha256 digest.
+pub fn check_image_reference(reference: &str) -> Result<()> {
+    if reference.is_empty() || reference.contains(char::is_whitespace) {
+        bail!("Invalid image reference {:?}", reference)
+    }
+    if let Some((_, digest)) = reference.split_once('@') {
+        let valid = digest
+            .strip_prefix("sha256:")
+            .is_some_and(|x| x.len() == 64 && x.chars().all(|x| x.is_ascii_hexdigit()));
+        if !valid {
+            bail!(
+                "Invalid digest {:?} in the image reference {:?}. Use 'sha256:' followed by 64 hexadecimal characters.",
+                digest,
+                reference
+            )
+        }
+    }
+
+    Ok(())
+}
+
+/// Read the image reference in an `.image` file
+///
+/// This is the first line that is not empty nor a `#` comment.
+pub fn read_image_file(path: &Path) -> Result<String> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Cannot read {}", path.to_string_lossy()))?;
+    let reference = content
+        .lines()
+        .map(|x| x.trim())
+        .find(|x| !x.is_empty() && !x.starts_with('#'));
+
+    match reference {
+        Some(reference) => {
+            check_image_reference(reference)
+                .with_context(|| format!("Invalid image in {}", path.to_string_lossy()))?;
+            Ok(reference.to_string())
+        }
+        None => bail!("There is no image in {}", path.to_string_lossy()),
+    }
+}
+
+#[test]
+fn test_check_image_reference() {
+    let digest = "sha256:".to_string() + &"a1".repeat(32);
+
+    assert!(check_image_reference("ubuntu").is_ok());
+    assert!(check_image_reference("ghcr.io/org/tool:1.4").is_ok());
+    assert!(check_image_reference(&format!("ghcr.io/org/tool:1.4@{}", digest)).is_ok());
+
+    assert!(check_image_reference("").is_err());
+    assert!(check_image_reference("my tool").is_err());
+    assert!(check_image_reference("tool@sha256:1234").is_err());
+    assert!(check_image_reference(&format!("tool@md5:{}", &digest[7..])).is_err());
+}
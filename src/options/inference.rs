@@ -0,0 +1,275 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Result};
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::utils::find_files;
+
+/// The options of the `[data.inference]` section
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct InferenceOptions {
+    /// The rules used to infer the test profile, in order of priority
+    #[serde(default)]
+    pub rules: Vec<InferenceRule>,
+}
+
+/// A rule to find the test version of input files, for the inferred 'test'
+/// profile.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(try_from = "InferenceRuleOptions")]
+pub enum InferenceRule {
+    /// `test_foo.txt` replaces `foo.txt`, in the same directory
+    Prefix(String),
+    /// `foo_test.txt` replaces `foo.txt`, in the same directory.
+    ///
+    /// The suffix goes before the extensions of the file.
+    Suffix(String),
+    /// `<directory>/foo/bar.txt` replaces `foo/bar.txt` in the input directory
+    ///
+    /// The directory is relative to the project root.
+    Directory(PathBuf),
+    /// Input paths that match the regex replace the path obtained by
+    /// replacing the match with `original` (e.g. `$1`). Paths are relative
+    /// to the input directory.
+    Regex { regex: Regex, original: String },
+}
+
+/// An inference rule, as written in the kerblam.toml file
+#[derive(Debug, Deserialize)]
+struct InferenceRuleOptions {
+    prefix: Option<String>,
+    suffix: Option<String>,
+    directory: Option<PathBuf>,
+    regex: Option<String>,
+    original: Option<String>,
+}
+
+impl TryFrom<InferenceRuleOptions> for InferenceRule {
+    type Error = anyhow::Error;
+
+    fn try_from(value: InferenceRuleOptions) -> Result<Self> {
+        let kinds = [
+            value.prefix.is_some(),
+            value.suffix.is_some(),
+            value.directory.is_some(),
+            value.regex.is_some(),
+        ];
+        if kinds.iter().filter(|x| **x).count() != 1 {
+            bail!(
+                "An inference rule needs exactly one of 'prefix', 'suffix', 'directory' or 'regex'"
+            )
+        }
+        if value.original.is_some() != value.regex.is_some() {
+            bail!("The 'original' of an inference rule is needed with, and only with, 'regex'")
+        }
+
+        if let Some(prefix) = value.prefix {
+            return Ok(Self::Prefix(prefix));
+        }
+        if let Some(suffix) = value.suffix {
+            return Ok(Self::Suffix(suffix));
+        }
+        if let Some(directory) = value.directory {
+            return Ok(Self::Directory(directory));
+        }
+        let pattern = value.regex.unwrap();
+        let regex = Regex::new(&pattern)
+            .map_err(|e| anyhow!("Invalid inference regex {:?}: {}", pattern, e))?;
+
+        Ok(Self::Regex {
+            regex,
+            original: value.original.unwrap(),
+        })
+    }
+}
+
+impl Display for InferenceRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Prefix(x) => write!(f, "prefix {:?}", x),
+            Self::Suffix(x) => write!(f, "suffix {:?}", x),
+            Self::Directory(x) => write!(f, "directory {:?}", x),
+            Self::Regex { regex, original } => {
+                write!(f, "regex {:?} -> {:?}", regex.as_str(), original)
+            }
+        }
+    }
+}
+
+/// The rules used when none are configured
+pub fn default_rules() -> Vec<InferenceRule> {
+    vec![InferenceRule::Prefix("test_".to_string())]
+}
+
+impl InferenceRule {
+    /// Find the original that an input file replaces, if any
+    ///
+    /// Both paths are relative to the input directory. The original might
+    /// not exist: this only applies the rule.
+    fn original_of(&self, path: &Path) -> Option<PathBuf> {
+        let file_name = path.file_name()?.to_string_lossy();
+        match self {
+            Self::Prefix(prefix) => {
+                let slug = file_name.strip_prefix(prefix.as_str())?;
+                (!slug.is_empty()).then(|| path.with_file_name(slug))
+            }
+            Self::Suffix(suffix) => {
+                let (stem, extensions) = match file_name.split_once('.') {
+                    Some((stem, extensions)) => (stem, format!(".{}", extensions)),
+                    None => (file_name.as_ref(), String::new()),
+                };
+                let slug = stem.strip_suffix(suffix.as_str())?;
+                (!slug.is_empty()).then(|| path.with_file_name(format!("{}{}", slug, extensions)))
+            }
+            Self::Directory(_) => None,
+            Self::Regex { regex, original } => {
+                let path = path.to_string_lossy();
+                regex
+                    .is_match(&path)
+                    .then(|| PathBuf::from(regex.replace(&path, original).as_ref()))
+            }
+        }
+    }
+}
+
+/// A pair of files inferred by a rule
+#[derive(Debug, Clone, PartialEq)]
+pub struct InferredPair {
+    /// The original input file
+    pub original: PathBuf,
+    /// The file that replaces it
+    pub target: PathBuf,
+    /// The index of the rule that inferred this pair
+    pub rule: usize,
+}
+
+/// Infer the pairs of the test profile from a list of input files
+///
+/// All paths are absolute. Only pairs where both files exist are kept, and
+/// if more than one rule pairs the same original, the first rule wins.
+pub fn infer_pairs(
+    rules: &[InferenceRule],
+    root: &Path,
+    input_dir: &Path,
+    input_files: &[PathBuf],
+) -> Vec<InferredPair> {
+    // Files in test directories are never originals
+    let test_dirs: Vec<PathBuf> = rules
+        .iter()
+        .filter_map(|x| match x {
+            InferenceRule::Directory(dir) => Some(root.join(dir)),
+            _ => None,
+        })
+        .collect();
+    let originals: HashSet<&PathBuf> = input_files
+        .iter()
+        .filter(|x| !test_dirs.iter().any(|dir| x.starts_with(dir)))
+        .collect();
+
+    let mut pairs: HashMap<PathBuf, InferredPair> = HashMap::new();
+    for (i, rule) in rules.iter().enumerate() {
+        let mut found: Vec<(PathBuf, PathBuf)> = vec![];
+        match rule {
+            InferenceRule::Directory(dir) => {
+                let dir = root.join(dir);
+                for target in find_files(&dir, None) {
+                    let relative = target.strip_prefix(&dir).unwrap();
+                    found.push((input_dir.join(relative), target.clone()));
+                }
+            }
+            _ => {
+                for target in &originals {
+                    let relative = match target.strip_prefix(input_dir) {
+                        Ok(x) => x,
+                        Err(_) => continue,
+                    };
+                    if let Some(original) = rule.original_of(relative) {
+                        found.push((input_dir.join(original), target.to_path_buf()));
+                    }
+                }
+            }
+        }
+
+        for (original, target) in found {
+            if original == target || !originals.contains(&original) {
+                continue;
+            }
+            pairs.entry(original.clone()).or_insert(InferredPair {
+                original,
+                target,
+                rule: i,
+            });
+        }
+    }
+
+    let mut pairs: Vec<InferredPair> = pairs.into_values().collect();
+    pairs.sort_unstable_by(|a, b| a.original.cmp(&b.original));
+    pairs
+}
+
+#[test]
+fn test_infer_pairs() {
+    let root = PathBuf::from("/project");
+    let input_dir = root.join("data/in");
+    let files: Vec<PathBuf> = [
+        "data/in/a.csv",
+        "data/in/test_a.csv",
+        "data/in/b.fastq.gz",
+        "data/in/b_small.fastq.gz",
+        "data/in/c.txt",
+        "data/in/c.tiny.txt",
+        "data/in/test_orphan.txt",
+    ]
+    .iter()
+    .map(|x| root.join(x))
+    .collect();
+    let rules = vec![
+        InferenceRule::Prefix("test_".into()),
+        InferenceRule::Suffix("_small".into()),
+        InferenceRule::Regex {
+            regex: Regex::new(r"^(.*)\.tiny(\..*)$").unwrap(),
+            original: "$1$2".into(),
+        },
+    ];
+
+    let pairs = infer_pairs(&rules, &root, &input_dir, &files);
+    let simple: Vec<(String, String, usize)> = pairs
+        .iter()
+        .map(|x| {
+            (
+                x.original
+                    .strip_prefix(&input_dir)
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string(),
+                x.target
+                    .strip_prefix(&input_dir)
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string(),
+                x.rule,
+            )
+        })
+        .collect();
+    assert_eq!(
+        simple,
+        vec![
+            ("a.csv".into(), "test_a.csv".into(), 0),
+            ("b.fastq.gz".into(), "b_small.fastq.gz".into(), 1),
+            ("c.txt".into(), "c.tiny.txt".into(), 2),
+        ]
+    );
+
+    let rule: Result<InferenceRule> = InferenceRuleOptions {
+        prefix: Some("test_".into()),
+        suffix: None,
+        directory: None,
+        regex: None,
+        original: Some("$1".into()),
+    }
+    .try_into();
+    assert!(rule.is_err());
+}
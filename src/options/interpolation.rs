@@ -0,0 +1,114 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use homedir::get_my_home;
+
+/// Expand environment variables and the home directory in a string.
+///
+/// Supports:
+/// - `${VAR}`, replaced by the value of `VAR` (even if empty), failing if it
+///   is not set;
+/// - `${VAR:-default}`, replaced by the value of `VAR`, or by `default` if
+///   `VAR` is unset or empty;
+/// - `$$`, replaced by a literal `$`;
+/// - A leading `~` (as in `~` or `~/some/path`), replaced by the home directory.
+pub fn interpolate(value: &str) -> Result<String> {
+    let home = get_my_home().ok().flatten();
+    interpolate_with(value, |name| env::var(name).ok(), home)
+}
+
+/// Identical to `interpolate`, but with the environment and home dir injected.
+fn interpolate_with(
+    value: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+    home: Option<PathBuf>,
+) -> Result<String> {
+    let mut result = String::with_capacity(value.len());
+
+    let rest = if value == "~" || value.starts_with("~/") {
+        let home = home.ok_or(anyhow!("Cannot expand '~': home directory not found"))?;
+        result.push_str(&home.to_string_lossy());
+        &value[1..]
+    } else {
+        value
+    };
+
+    let mut chars = rest.chars().peekable();
+    while let Some(char) = chars.next() {
+        if char != '$' {
+            result.push(char);
+            continue;
+        }
+        match chars.peek() {
+            Some('$') => {
+                chars.next();
+                result.push('$');
+            }
+            Some('{') => {
+                chars.next();
+                let mut expression = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(x) => expression.push(x),
+                        None => bail!("Unterminated '${{' in {:?}", value),
+                    }
+                }
+                let (name, default) = match expression.split_once(":-") {
+                    Some((name, default)) => (name, Some(default)),
+                    None => (expression.as_str(), None),
+                };
+                if name.is_empty() {
+                    bail!("Empty variable name in {:?}", value)
+                }
+                // Like in the shell, empty values only count as unset when
+                // there is a default
+                match (lookup(name), default) {
+                    (Some(found), Some(default)) if found.is_empty() => result.push_str(default),
+                    (Some(found), _) => result.push_str(&found),
+                    (None, Some(default)) => result.push_str(default),
+                    (None, None) => bail!("Undefined environment variable '{}'", name),
+                }
+            }
+            // A lone '$' is left as-is.
+            _ => result.push(char),
+        }
+    }
+
+    Ok(result)
+}
+
+/// Interpolate a path, adding the name of the field to any error
+pub fn interpolate_path(path: &Path, field: &str) -> Result<PathBuf> {
+    interpolate(&path.to_string_lossy())
+        .map(PathBuf::from)
+        .with_context(|| format!("Failed to expand the value of `{}` in kerblam.toml", field))
+}
+
+#[test]
+fn test_interpolation() {
+    let lookup = |name: &str| match name {
+        "SCRATCH" => Some("/scratch/me".to_string()),
+        "EMPTY" => Some(String::new()),
+        _ => None,
+    };
+    let home = Some(PathBuf::from("/home/me"));
+    let expand = |x: &str| interpolate_with(x, lookup, home.clone());
+
+    assert_eq!(expand("data/in").unwrap(), "data/in");
+    assert_eq!(expand("${SCRATCH}/in").unwrap(), "/scratch/me/in");
+    assert_eq!(expand("${MISSING:-/tmp}/in").unwrap(), "/tmp/in");
+    assert_eq!(expand("${EMPTY:-fallback}").unwrap(), "fallback");
+    assert_eq!(expand("a${EMPTY}b").unwrap(), "ab");
+    assert_eq!(expand("~/data").unwrap(), "/home/me/data");
+    assert_eq!(expand("~").unwrap(), "/home/me");
+    assert_eq!(expand("a~b").unwrap(), "a~b");
+    assert_eq!(expand("$$HOME and $5").unwrap(), "$HOME and $5");
+
+    assert!(expand("${MISSING}/in")
+        .unwrap_err()
+        .to_string()
+        .contains("MISSING"));
+    assert!(expand("${SCRATCH").is_err());
+}
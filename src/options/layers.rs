@@ -0,0 +1,197 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use toml::{Table, Value};
+
+use super::validation::{join_dotted, validate_kerblam_toml};
+
+/// The name of the local, per-machine override of the kerblam.toml file
+pub const LOCAL_CONFIG_NAME: &str = "kerblam.local.toml";
+
+/// The environment variable with a list of extra configuration files
+pub const CONFIG_ENV_VAR: &str = "KERBLAM_CONFIG";
+
+/// The configuration of a project, made by layering several TOML files.
+#[derive(Debug, Clone)]
+pub struct ConfigLayers {
+    /// The files that were merged, from the lowest to the highest precedence
+    pub files: Vec<PathBuf>,
+    /// The deep-merged content of all the files
+    pub merged: Table,
+    /// For each value (as a dotted key), the file that it comes from
+    pub origins: BTreeMap<String, PathBuf>,
+}
+
+impl ConfigLayers {
+    /// Read, validate and merge a list of TOML files.
+    ///
+    /// Later files take precedence over earlier ones.
+    /// Fails listing all issues found if any of the files is not valid.
+    pub fn from_files(files: &[PathBuf]) -> Result<Self> {
+        let mut layers = ConfigLayers {
+            files: vec![],
+            merged: Table::new(),
+            origins: BTreeMap::new(),
+        };
+
+        for file in files {
+            log::debug!("Reading {:?} for TOML options...", file);
+            let content = String::from_utf8(
+                fs::read(file).with_context(|| format!("Cannot read config file {:?}", file))?,
+            )?;
+
+            let issues = validate_kerblam_toml(&content, file);
+            if !issues.is_empty() {
+                bail!(
+                    "Invalid kerblam.toml file:\n{}",
+                    issues
+                        .iter()
+                        .map(|x| format!("\t- {}", x))
+                        .collect::<Vec<String>>()
+                        .join("\n")
+                )
+            }
+
+            let table: Table = toml::from_str(&content)?;
+            merge_tables(&mut layers.merged, table, "", file, &mut layers.origins);
+            layers.files.push(file.to_owned());
+        }
+
+        Ok(layers)
+    }
+}
+
+/// Deep-merge the `incoming` table into `base`, recording where values come from.
+///
+/// Tables are merged key by key, while any other value (including arrays)
+/// replaces the previous one wholesale.
+fn merge_tables(
+    base: &mut Table,
+    incoming: Table,
+    dotted: &str,
+    file: &Path,
+    origins: &mut BTreeMap<String, PathBuf>,
+) {
+    for (key, value) in incoming {
+        let child = join_dotted(dotted, &key);
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(old)), Value::Table(new)) => {
+                merge_tables(old, new, &child, file, origins);
+            }
+            (_, Value::Table(new)) => {
+                forget_origins(origins, &child);
+                let mut table = Table::new();
+                merge_tables(&mut table, new, &child, file, origins);
+                base.insert(key, Value::Table(table));
+            }
+            (_, value) => {
+                forget_origins(origins, &child);
+                origins.insert(child, file.to_owned());
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Remove the origins of a key, and of all keys nested under it
+fn forget_origins(origins: &mut BTreeMap<String, PathBuf>, dotted: &str) {
+    let nested = format!("{}.", dotted);
+    origins.retain(|key, _| key != dotted && !key.starts_with(&nested));
+}
+
+/// Return the list of configuration files that apply to a kerblam.toml file
+///
+/// These are, in order of precedence (lowest first):
+/// - The kerblam.toml file itself;
+/// - The `kerblam.local.toml` file next to it, if it exists;
+/// - The files listed in the `KERBLAM_CONFIG` environment variable, separated
+///   like the `PATH` variable (e.g. by `:` on Unix).
+///   Relative paths are relative to the location of the kerblam.toml file.
+pub fn config_files(toml_file: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+    let toml_file = toml_file.as_ref();
+    let root = toml_file.parent().unwrap_or(Path::new("."));
+    let mut files = vec![toml_file.to_path_buf()];
+
+    let local = root.join(LOCAL_CONFIG_NAME);
+    if local.exists() {
+        files.push(local);
+    }
+
+    if let Some(extra) = env::var_os(CONFIG_ENV_VAR) {
+        for path in env::split_paths(&extra) {
+            if path.as_os_str().is_empty() {
+                continue;
+            }
+            let path = root.join(path);
+            if !path.exists() {
+                bail!(
+                    "Config file {:?} (from the {} variable) does not exist.",
+                    path,
+                    CONFIG_ENV_VAR
+                )
+            }
+            files.push(path);
+        }
+    }
+
+    log::debug!("Configuration files: {files:?}");
+
+    Ok(files)
+}
+
+#[test]
+fn test_merge_tables() {
+    let base: Table = toml::from_str(
+        r#"
+[data.paths]
+input = "data/in"
+output = "data/out"
+
+[data.profiles.test]
+"a.txt" = "test_a.txt"
+
+[execution]
+backend = "docker"
+"#,
+    )
+    .unwrap();
+    let local: Table = toml::from_str(
+        r#"
+data.paths.input = "/scratch/in"
+execution.backend = "podman"
+data.profiles.test = { "b.txt" = "test_b.txt" }
+"#,
+    )
+    .unwrap();
+
+    let mut merged = Table::new();
+    let mut origins = BTreeMap::new();
+    merge_tables(&mut merged, base, "", Path::new("base"), &mut origins);
+    merge_tables(&mut merged, local, "", Path::new("local"), &mut origins);
+
+    assert_eq!(
+        merged["data"]["paths"]["input"].as_str(),
+        Some("/scratch/in")
+    );
+    assert_eq!(merged["data"]["paths"]["output"].as_str(), Some("data/out"));
+    assert_eq!(merged["execution"]["backend"].as_str(), Some("podman"));
+    // Tables are merged, not replaced
+    assert_eq!(
+        merged["data"]["profiles"]["test"].as_table().unwrap().len(),
+        2
+    );
+
+    assert_eq!(origins["data.paths.input"], PathBuf::from("local"));
+    assert_eq!(origins["data.paths.output"], PathBuf::from("base"));
+    assert_eq!(
+        origins["data.profiles.test.\"a.txt\""],
+        PathBuf::from("base")
+    );
+    assert_eq!(
+        origins["data.profiles.test.\"b.txt\""],
+        PathBuf::from("local")
+    );
+}
@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+
+use super::params::{ParamOptions, PipeParam};
+
+/// The options of a single pipe, in the `[pipes]` section of the
+/// kerblam.toml file. These add to the metadata in its docstring.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct PipeOptions {
+    #[serde(default)]
+    pub params: HashMap<String, ParamOptions>,
+    /// The pipes that must be run before this one
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// The container image to run the pipe in, instead of building one
+    pub image: Option<String>,
+}
+
+/// Structured information about a pipe, from the `#? @key: value` lines of
+/// its docstring.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PipeMetadata {
+    /// The container to use (e.g. `alignment` for `alignment.dockerfile`),
+    /// instead of the one with the same name as the pipe
+    pub env: Option<String>,
+    /// The pipe can only be run with a data profile
+    pub profile_required: bool,
+    /// The files that the pipe creates, relative to the project root
+    pub outputs: Vec<PathBuf>,
+    /// How long the pipe can run for before being stopped
+    pub timeout: Option<Duration>,
+    /// The parameters that the pipe accepts
+    pub params: Vec<PipeParam>,
+    /// The pipes that must be run before this one
+    pub depends_on: Vec<String>,
+    /// The container image to run the pipe in, instead of building one.
+    /// This is set in the kerblam.toml file, or by an `.image` file.
+    pub image: Option<String>,
+}
+
+/// The keys of the metadata lines, like `@env` in `@env: alignment`
+pub const METADATA_KEYS: [&str; 6] = [
+    "env",
+    "profile-required",
+    "outputs",
+    "timeout",
+    "param",
+    "depends-on",
+];
+
+/// Split a metadata line in its key and value, if it has one
+fn split_metadata_line(line: &str) -> (&str, Option<&str>) {
+    let line = line.trim().trim_start_matches('@');
+    match line.split_once(':') {
+        Some((key, value)) => (key.trim(), Some(value.trim())),
+        None => (line.trim(), None),
+    }
+}
+
+/// Is this docstring line (without the docstring marker) a metadata line?
+///
+/// Only lines with one of the `METADATA_KEYS` are, so that other lines that
+/// start with `@` (e.g. `@author notes`) are left in the description.
+pub fn is_metadata_line(text: &str) -> bool {
+    text.trim_start().starts_with('@') && METADATA_KEYS.contains(&split_metadata_line(text).0)
+}
+
+impl PipeMetadata {
+    /// Parse the metadata lines of a docstring, without the docstring marker
+    ///
+    /// For instance, `@env: alignment` or `@profile-required`.
+    pub fn parse(lines: &[String]) -> Result<Self> {
+        let mut metadata = Self::default();
+        for line in lines {
+            let (key, value) = split_metadata_line(line);
+            let value = value.filter(|x| !x.is_empty());
+            let needs_value = || value.ok_or(anyhow!("The '@{}' metadata needs a value", key));
+
+            match key {
+                "env" => {
+                    if metadata.env.is_some() {
+                        bail!("The '@env' metadata is set more than once")
+                    }
+                    metadata.env = Some(needs_value()?.to_string());
+                }
+                "profile-required" => {
+                    if value.is_some() {
+                        bail!("The '@profile-required' metadata takes no value")
+                    }
+                    metadata.profile_required = true;
+                }
+                "outputs" => metadata.outputs.extend(
+                    needs_value()?
+                        .split(',')
+                        .map(|x| x.trim())
+                        .filter(|x| !x.is_empty())
+                        .map(PathBuf::from),
+                ),
+                "timeout" => {
+                    if metadata.timeout.is_some() {
+                        bail!("The '@timeout' metadata is set more than once")
+                    }
+                    metadata.timeout = Some(parse_duration(needs_value()?)?);
+                }
+                "param" => {
+                    let param = PipeParam::from_header(needs_value()?)?;
+                    metadata.set_param(param);
+                }
+                "depends-on" => {
+                    for pipe in needs_value()?.split(',').map(|x| x.trim()) {
+                        metadata.add_dependency(pipe);
+                    }
+                }
+                _ => bail!(
+                    "Unknown pipe metadata '@{}'. Expected one of: @{}",
+                    key,
+                    METADATA_KEYS.join(", @")
+                ),
+            }
+        }
+
+        Ok(metadata)
+    }
+
+    /// Add a parameter, replacing the one with the same name if there is one
+    pub fn set_param(&mut self, param: PipeParam) {
+        match self.params.iter_mut().find(|x| x.name == param.name) {
+            Some(existing) => *existing = param,
+            None => self.params.push(param),
+        }
+    }
+
+    /// Add a pipe that must be run before this one, if it is not there yet
+    pub fn add_dependency(&mut self, pipe: &str) {
+        if !pipe.is_empty() && !self.depends_on.iter().any(|x| x == pipe) {
+            self.depends_on.push(pipe.to_string());
+        }
+    }
+
+    /// Is there any metadata at all?
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+impl Display for PipeMetadata {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut lines: Vec<String> = vec![];
+        if let Some(env) = &self.env {
+            lines.push(format!("- Container: `{}`", env));
+        }
+        if let Some(image) = &self.image {
+            lines.push(format!("- Image: `{}`", image));
+        }
+        if self.profile_required {
+            lines.push("- Must be run with a data profile".to_string());
+        }
+        if !self.outputs.is_empty() {
+            let outputs: Vec<String> = self
+                .outputs
+                .iter()
+                .map(|x| format!("`{}`", x.to_string_lossy()))
+                .collect();
+            lines.push(format!("- Outputs: {}", outputs.join(", ")));
+        }
+        if let Some(timeout) = self.timeout {
+            lines.push(format!("- Timeout: {}", format_duration(timeout)));
+        }
+        if !self.depends_on.is_empty() {
+            let pipes: Vec<String> = self.depends_on.iter().map(|x| format!("`{}`", x)).collect();
+            lines.push(format!("- Depends on: {}", pipes.join(", ")));
+        }
+        for param in &self.params {
+            lines.push(format!("- Parameter {}", param));
+        }
+
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+/// Parse a duration like `2h`, `1h30m` or `90s`
+///
+/// The units are `d`, `h`, `m` and `s`. A number without unit is in seconds.
+pub fn parse_duration(text: &str) -> Result<Duration> {
+    let invalid = || {
+        anyhow!(
+            "Invalid duration {:?}. Use something like '2h', '1h30m' or '90s'",
+            text
+        )
+    };
+
+    let mut seconds: u64 = 0;
+    let mut number = String::new();
+    for char in text.trim().chars() {
+        if char.is_ascii_digit() {
+            number.push(char);
+            continue;
+        }
+        let multiplier = match char {
+            'd' => 24 * 60 * 60,
+            'h' => 60 * 60,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(invalid()),
+        };
+        let value: u64 = number.parse().map_err(|_| invalid())?;
+        seconds = value
+            .checked_mul(multiplier)
+            .and_then(|x| seconds.checked_add(x))
+            .ok_or_else(invalid)?;
+        number.clear();
+    }
+    if !number.is_empty() {
+        let value: u64 = number.parse().map_err(|_| invalid())?;
+        seconds = seconds.checked_add(value).ok_or_else(invalid)?;
+    }
+
+    if seconds == 0 {
+        return Err(invalid());
+    }
+
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Show a duration in the same format as `parse_duration`
+pub fn format_duration(duration: Duration) -> String {
+    let mut seconds = duration.as_secs();
+    let mut text = String::new();
+    for (unit, size) in [("d", 24 * 60 * 60), ("h", 60 * 60), ("m", 60), ("s", 1)] {
+        if seconds >= size {
+            text.push_str(&format!("{}{}", seconds / size, unit));
+            seconds %= size;
+        }
+    }
+
+    text
+}
+
+#[test]
+fn test_parse_metadata() {
+    let lines: Vec<String> = [
+        "@env: alignment",
+        " @profile-required",
+        "@outputs: data/out/counts.csv, data/out/plot.png",
+        "@outputs: data/out/log.txt",
+        "@timeout: 1h30m",
+    ]
+    .iter()
+    .map(|x| x.to_string())
+    .collect();
+    let metadata = PipeMetadata::parse(&lines).unwrap();
+
+    assert_eq!(
+        metadata,
+        PipeMetadata {
+            env: Some("alignment".into()),
+            profile_required: true,
+            outputs: vec![
+                "data/out/counts.csv".into(),
+                "data/out/plot.png".into(),
+                "data/out/log.txt".into()
+            ],
+            timeout: Some(Duration::from_secs(90 * 60)),
+            params: vec![],
+            depends_on: vec![],
+            image: None,
+        }
+    );
+    assert_eq!(format_duration(metadata.timeout.unwrap()), "1h30m");
+
+    assert!(PipeMetadata::parse(&["@colour: blue".to_string()]).is_err());
+    assert!(is_metadata_line(" @timeout: 2h"));
+    assert!(!is_metadata_line("@author notes"));
+    assert!(PipeMetadata::parse(&["@timeout: soon".to_string()]).is_err());
+    assert!(parse_duration("99999999999999999h").is_err());
+    assert!(parse_duration("18446744073709551615s1s").is_err());
+    assert!(PipeMetadata::parse(&["@env".to_string()]).is_err());
+}
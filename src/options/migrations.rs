@@ -0,0 +1,124 @@
+use std::path::Path;
+
+use anyhow::Result;
+use toml_edit::DocumentMut;
+
+use super::editing::set_dotted;
+use crate::utils::parse_version;
+use crate::VERSION;
+
+/// A change to the kerblam.toml schema, and how to update old files to it.
+pub struct Migration {
+    /// The first version of Kerblam! that uses the new schema
+    pub version: &'static str,
+    /// What changed, and what the migration does about it
+    pub description: &'static str,
+    /// Update a document to the new schema.
+    ///
+    /// Returns whether the document was changed: a migration might not be
+    /// needed even if the file is older than `version`.
+    /// Migrations run in the project root, so they can inspect the project.
+    pub apply: fn(&mut DocumentMut) -> Result<bool>,
+}
+
+/// All the known migrations, oldest first.
+pub const MIGRATIONS: &[Migration] = &[Migration {
+    version: "1.0.0-rc.3",
+    description: "The default workflows directory changed from 'src/pipes' to 'src/workflows'. \
+        If the project still uses 'src/pipes', `code.pipes_dir` is set to it.",
+    apply: pin_old_pipes_dir,
+}];
+
+fn pin_old_pipes_dir(document: &mut DocumentMut) -> Result<bool> {
+    let is_set = document
+        .get("code")
+        .and_then(|x| x.get("pipes_dir"))
+        .is_some();
+    if is_set || !Path::new("src/pipes").is_dir() {
+        return Ok(false);
+    }
+
+    set_dotted(document, "code.pipes_dir", "src/pipes".into())?;
+    Ok(true)
+}
+
+/// Return the migrations needed by a file written for some Kerblam! version
+///
+/// Unparsable versions need no migrations, as we cannot tell how old they are.
+pub fn migrations_since(version: &str) -> Vec<&'static Migration> {
+    let from = match parse_version(version) {
+        Ok(x) => x,
+        Err(_) => return vec![],
+    };
+
+    MIGRATIONS
+        .iter()
+        .filter(|x| parse_version(x.version).is_ok_and(|to| from < to))
+        .collect()
+}
+
+/// Migrate a document to the current schema, and bump its `meta.version`.
+///
+/// Returns the migrations that changed the document.
+/// Documents with no `meta.version` only get their version set.
+pub fn migrate(document: &mut DocumentMut) -> Result<Vec<&'static Migration>> {
+    let version = document
+        .get("meta")
+        .and_then(|x| x.get("version"))
+        .and_then(|x| x.as_str())
+        .map(|x| x.to_string());
+
+    let mut applied = vec![];
+    if let Some(version) = version {
+        for migration in migrations_since(&version) {
+            if (migration.apply)(document)? {
+                applied.push(migration);
+            }
+        }
+    }
+
+    set_dotted(document, "meta.version", VERSION.into())?;
+
+    Ok(applied)
+}
+
+/// Warn the user if the configuration needs some migrations
+///
+/// This does not touch any file: it only explains what is incompatible.
+pub fn warn_pending_migrations(config: &toml::Table) {
+    let mut document: DocumentMut = match toml::to_string(config).ok().and_then(|x| x.parse().ok())
+    {
+        Some(x) => x,
+        None => return,
+    };
+
+    let pending = match migrate(&mut document) {
+        Ok(x) => x,
+        Err(_) => return,
+    };
+    if pending.is_empty() {
+        return;
+    }
+
+    eprintln!(
+        "⚠️  The kerblam.toml file uses an old schema, and is incompatible with this version:"
+    );
+    for migration in pending {
+        eprintln!(
+            "    - (since {}) {}",
+            migration.version, migration.description
+        );
+    }
+    eprintln!("   Run `kerblam config migrate` to update it.");
+}
+
+#[test]
+fn test_migrations_since() {
+    assert_eq!(migrations_since("0.5.1").len(), 1);
+    assert_eq!(migrations_since("0.5").len(), 1);
+    assert_eq!(migrations_since("1.0.0-rc.2").len(), 1);
+    assert_eq!(migrations_since("1.0.0-rc.3").len(), 0);
+    assert_eq!(migrations_since("1.2.1").len(), 0);
+    assert_eq!(migrations_since("1.2").len(), 0);
+    assert_eq!(migrations_since("not a version").len(), 0);
+}
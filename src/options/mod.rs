@@ -0,0 +1,1070 @@
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::cell::OnceCell;
+use std::env::{current_dir, set_current_dir};
+use std::fmt::Display;
+use std::fmt::Write;
+use std::fs::File;
+use std::io::{self, BufRead};
+use std::path::Path;
+use std::{collections::HashMap, path::PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use toml::Value;
+use url::Url;
+
+use crate::execution::{Environment, ExecutionStrategy, Executor};
+use crate::utils::{find_files, warn_kerblam_version};
+
+mod dependencies;
+mod editing;
+mod images;
+mod inference;
+mod interpolation;
+mod layers;
+mod metadata;
+mod migrations;
+mod params;
+mod patterns;
+mod pipelines;
+mod profiles;
+mod validation;
+
+pub use dependencies::dependency_order;
+pub use editing::set_dotted;
+use images::{check_image_reference, read_image_file};
+pub use inference::{InferenceOptions, InferenceRule, InferredPair};
+use interpolation::{interpolate, interpolate_path};
+pub use layers::{config_files, ConfigLayers, LOCAL_CONFIG_NAME};
+use metadata::is_metadata_line;
+pub use metadata::{format_duration, PipeMetadata, PipeOptions};
+pub use migrations::migrate;
+use migrations::warn_pending_migrations;
+pub use params::resolve_params;
+pub use pipelines::{PipelineOptions, PipelineStep};
+pub use profiles::{
+    describe_profile, expand_profile_paths, extract_profile_paths, missing_profile_paths,
+    ProfileMode, ProfileOptions,
+};
+pub use validation::{validate_kerblam_toml, Schema, KERBLAM_TOML_SCHEMA};
+
+/// The default input data directory, relative to the project root
+pub const DEFAULT_INPUT_DIR: &str = "data/in";
+/// The default output data directory, relative to the project root
+pub const DEFAULT_OUTPUT_DIR: &str = "data/out";
+/// The default intermediate data directory, relative to the project root
+pub const DEFAULT_INTERMEDIATE_DIR: &str = "data";
+/// The default directory with the workflows, relative to the project root
+pub const DEFAULT_PIPES_DIR: &str = "src/workflows";
+/// The default directory with the container files, relative to the project root
+pub const DEFAULT_ENV_DIR: &str = "src/dockerfiles";
+/// The default working directory inside containers
+pub const DEFAULT_WORKDIR: &str = "/";
+
+/// All the single-valued settings of the kerblam.toml file, with their default
+///
+/// The resolved value of these can be obtained with `KerblamTomlOptions::setting`.
+pub const SETTINGS: [(&str, Option<&str>); 10] = [
+    ("meta.version", None),
+    ("meta.requires", None),
+    ("data.paths.input", Some(DEFAULT_INPUT_DIR)),
+    ("data.paths.output", Some(DEFAULT_OUTPUT_DIR)),
+    ("data.paths.intermediate", Some(DEFAULT_INTERMEDIATE_DIR)),
+    ("code.pipes_dir", Some(DEFAULT_PIPES_DIR)),
+    ("code.env_dir", Some(DEFAULT_ENV_DIR)),
+    ("execution.backend", Some("docker")),
+    ("execution.workdir", Some(DEFAULT_WORKDIR)),
+    ("execution.offline", Some("false")),
+];
+
+// Note: i keep all the fields that are not used to private until we
+// actually support their usage.
+
+// TODO: Consider using serde defaults here instead of some of the options.
+//
+// TODO: Apparently something called 'lenses' could really help reduce
+//      most of this code by allowing access to nested 'Option'-heavy structs
+//      but for the love of god I can't get it to work.
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct DataPaths {
+    input: Option<PathBuf>,
+    output: Option<PathBuf>,
+    intermediate: Option<PathBuf>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct DataOptions {
+    pub paths: Option<DataPaths>,
+    // Profiles are like HashMap<profile_name, ProfileOptions>, with the
+    // ProfileOptions holding the old_file_name: new_file_name pairs
+    pub profiles: Option<HashMap<String, ProfileOptions>>,
+    pub profiles_mode: Option<ProfileMode>,
+    /// How to infer the 'test' profile
+    pub inference: Option<InferenceOptions>,
+    pub remote: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct CodeOptions {
+    pub env_dir: Option<PathBuf>,
+    pub pipes_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Meta {
+    pub version: Option<String>,
+    pub requires: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct KerblamTomlOptions {
+    pub meta: Option<Meta>,
+    pub data: Option<DataOptions>,
+    pub code: Option<CodeOptions>,
+    #[serde(default)]
+    pub execution: ExecutionOptions,
+    /// Options for specific pipes, by name, from the `[pipes]` section
+    #[serde(default, rename = "pipes")]
+    pub pipe_options: HashMap<String, PipeOptions>,
+    /// Sequences of pipes that are run together, by name
+    #[serde(default)]
+    pub pipelines: HashMap<String, PipelineOptions>,
+    /// All the profiles, once they are found (see `profiles`)
+    #[serde(skip)]
+    profiles_cache: OnceCell<HashMap<String, ProfileOptions>>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ExecutionOptions {
+    #[serde(default)]
+    pub backend: ContainerBackend,
+    pub workdir: Option<PathBuf>,
+    /// The commands used to run scripts, by file extension
+    #[serde(default)]
+    pub interpreters: HashMap<String, String>,
+    /// Never pull container images, only use the local ones
+    #[serde(default)]
+    pub offline: bool,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ContainerBackend {
+    Docker,
+    Podman,
+    Apptainer,
+    Singularity,
+}
+
+impl ContainerBackend {
+    /// Does this backend have a docker-like command line (`build -f`, `run -v`)?
+    ///
+    /// If not, it is Apptainer (or Singularity, its former name), that builds
+    /// images to files and runs them with `exec --bind`.
+    pub fn is_docker_like(&self) -> bool {
+        matches!(self, Self::Docker | Self::Podman)
+    }
+
+    /// The extensions of the container files that this backend can build,
+    /// from the most to the least preferred.
+    ///
+    /// `.image` files, with the reference to an existing image, come last.
+    pub fn container_extensions(&self) -> &'static [&'static str] {
+        if self.is_docker_like() {
+            &["dockerfile", "image"]
+        } else {
+            // Dockerfiles are converted to definition files
+            &["def", "dockerfile", "image"]
+        }
+    }
+}
+
+impl Default for ContainerBackend {
+    fn default() -> Self {
+        Self::Docker
+    }
+}
+
+impl From<ContainerBackend> for String {
+    fn from(val: ContainerBackend) -> Self {
+        match val {
+            ContainerBackend::Docker => "docker".into(),
+            ContainerBackend::Podman => "podman".into(),
+            ContainerBackend::Apptainer => "apptainer".into(),
+            ContainerBackend::Singularity => "singularity".into(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RemoteFile {
+    pub url: Option<Url>,
+    pub path: PathBuf,
+}
+
+impl From<RemoteFile> for PathBuf {
+    fn from(val: RemoteFile) -> Self {
+        val.path
+    }
+}
+
+impl TryFrom<RemoteFile> for Url {
+    fn try_from(value: RemoteFile) -> Result<Self> {
+        value
+            .url
+            .ok_or(anyhow!("Cannot convert to URL - it's missing"))
+    }
+
+    type Error = anyhow::Error;
+}
+
+impl RemoteFile {
+    fn from_string_pair(value: (&str, &str), base_directory: PathBuf) -> Result<Self> {
+        if value.0 == "_" {
+            Ok(RemoteFile {
+                url: None,
+                path: base_directory.join(value.1),
+            })
+        } else {
+            Ok(RemoteFile {
+                url: Some(Url::try_from(value.0)?),
+                path: base_directory.join(value.1),
+            })
+        }
+    }
+}
+
+/// The markers of the description lines of pipes, for the comment syntax of
+/// the various languages.
+const DOCSTRING_MARKERS: [&str; 3] = ["#?", "//?", "--?"];
+
+#[derive(Debug, Clone)]
+pub struct Pipe {
+    /// The name of the pipe, namespaced by the folder it is in
+    pub name: String,
+    pub pipe_path: PathBuf,
+    pub env_path: Option<PathBuf>,
+    /// The metadata from the `@key` lines of the docstring
+    pub metadata: PipeMetadata,
+    /// Why the pipe cannot be run (e.g. invalid metadata), if it cannot
+    pub invalid: Option<String>,
+}
+
+pub struct PipeDescription {
+    pub header: String,
+    pub body: Option<String>,
+}
+
+impl PipeDescription {
+    fn from_text_box(text: String) -> Self {
+        let pieces: Vec<&str> = text.split("\n\n").map(|x| x.trim()).collect();
+
+        if pieces.len() == 1 {
+            let header = pieces[0].replace('\n', " ");
+            return PipeDescription { header, body: None };
+        }
+
+        let header = pieces[0].replace('\n', " ");
+        let body: String = pieces[1..].join("\n");
+
+        PipeDescription {
+            header,
+            body: Some(body),
+        }
+    }
+}
+
+impl Pipe {
+    /// Obtain the name of the pipe
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    /// The folder that the pipe is in, if it is namespaced
+    pub fn namespace(&self) -> Option<String> {
+        self.name
+            .rsplit_once('/')
+            .map(|(namespace, _)| namespace.to_string())
+    }
+
+    /// Parse the file to obtain the description field
+    ///
+    /// The description is written in comment lines that start with one of
+    /// the `DOCSTRING_MARKERS`, e.g. `#?` for shell or python scripts.
+    /// Metadata lines (see `Pipe::read_metadata`) are not included.
+    pub fn description(&self) -> Result<Option<PipeDescription>> {
+        let mut text_box = String::new();
+        for text in read_docstring(&self.pipe_path)? {
+            if !is_metadata_line(&text) {
+                text_box.write_str(&format!("{}\n", text))?;
+            }
+        }
+
+        if text_box.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(PipeDescription::from_text_box(text_box)))
+    }
+
+    /// Fail if the pipe cannot be run, e.g. because its metadata is invalid
+    pub fn check(&self) -> Result<()> {
+        match &self.invalid {
+            Some(reason) => bail!("The pipe '{}' cannot be run: {}", self.name, reason),
+            None => Ok(()),
+        }
+    }
+
+    /// Parse the `#? @key: value` lines of the docstring of a pipe file
+    pub fn read_metadata(pipe_path: &Path) -> Result<PipeMetadata> {
+        let lines: Vec<String> = read_docstring(pipe_path)?
+            .into_iter()
+            .filter(|x| is_metadata_line(x))
+            .collect();
+
+        PipeMetadata::parse(&lines)
+            .with_context(|| format!("Invalid metadata in {}", pipe_path.to_string_lossy()))
+    }
+
+    pub fn into_executor(
+        self,
+        execution_dir: impl AsRef<Path>,
+        interpreters: &HashMap<String, String>,
+    ) -> std::result::Result<Executor, anyhow::Error> {
+        let execution_dir: PathBuf = execution_dir.as_ref().into();
+        let environment = match (self.metadata.image, self.env_path) {
+            (Some(image), _) => Some(Environment::Image(image)),
+            (None, Some(path)) => Some(Environment::File(path)),
+            (None, None) => None,
+        };
+        Executor::create(execution_dir, self.pipe_path, environment, interpreters)
+    }
+
+    /// Drop the environment file (and image) from this pipe
+    pub fn drop_env(self) -> Self {
+        Self {
+            name: self.name,
+            pipe_path: self.pipe_path,
+            env_path: None,
+            metadata: PipeMetadata {
+                image: None,
+                ..self.metadata
+            },
+            invalid: self.invalid,
+        }
+    }
+
+    /// Use another container file for this pipe
+    ///
+    /// If it is an `.image` file, the pipe runs in the image it refers to.
+    fn with_env(self, env_path: PathBuf) -> Result<Self> {
+        let image = image_file_reference(Some(&env_path))?;
+        Ok(Self {
+            env_path: Some(env_path),
+            metadata: PipeMetadata {
+                image,
+                ..self.metadata
+            },
+            ..self
+        })
+    }
+
+    /// Generate a long description for this pipe
+    pub fn long_description(self) -> String {
+        let desc = self
+            .description()
+            .expect("Could not parse description file");
+        let header = format!("{}", self);
+        let header = header.trim();
+
+        let description = match desc {
+            Some(desc) => match desc.body {
+                Some(body) => format!("{}\n{}", header, body),
+                None => header.to_string(),
+            },
+            None => "No description found.".to_string(),
+        };
+
+        let description = if self.metadata.is_empty() {
+            description
+        } else {
+            format!("{}\n\n{}", description, self.metadata)
+        };
+        match &self.invalid {
+            Some(reason) => format!("{}\n\n⚠️  This pipe cannot be run: {}", description, reason),
+            None => description,
+        }
+    }
+}
+
+/// Read the text of the docstring lines of a pipe file, without the markers
+fn read_docstring(pipe_path: &Path) -> Result<Vec<String>> {
+    let conn = File::open(pipe_path)?;
+    let lines = io::BufReader::new(conn);
+
+    let mut docstring = vec![];
+    for line in lines.lines() {
+        let line = line?;
+        let line = line.trim();
+        if let Some(text) = DOCSTRING_MARKERS.iter().find_map(|x| line.strip_prefix(x)) {
+            docstring.push(text.to_string());
+        }
+    }
+
+    Ok(docstring)
+}
+
+/// The image reference in a container file, if it is an `.image` file
+fn image_file_reference(env_path: Option<&PathBuf>) -> Result<Option<String>> {
+    match env_path {
+        Some(path) if path.extension().is_some_and(|x| x == "image") => {
+            Ok(Some(read_image_file(path)?))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// The name of a file in a folder, namespaced by its subfolders
+///
+/// E.g. `<root>/qc/run.sh` is `qc/run`. The extension is dropped.
+fn namespaced_name(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path).with_extension("");
+    relative
+        .components()
+        .map(|x| x.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+impl Display for Pipe {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let container_prefix = match (&self.env_path, &self.metadata.image) {
+            (None, None) => "◾",
+            (Some(path), _) if path.file_stem().unwrap() == "default" => "🐟",
+            _ => "🐋",
+        };
+        let desc_prefix = if self
+            .description()
+            .expect("Could not parse description file")
+            .is_some_and(|x| x.body.is_some())
+        {
+            "📜"
+        } else {
+            "◾"
+        };
+
+        let prefix = [container_prefix, desc_prefix].concat();
+        let name = if self.invalid.is_some() {
+            format!("{} (⚠️ invalid, see --desc)", self.name())
+        } else if self.metadata.profile_required {
+            format!("{} (needs a profile)", self.name())
+        } else {
+            self.name()
+        };
+
+        let desc = self
+            .description()
+            .expect("Could not parse file to look for description.");
+
+        match desc {
+            Some(desc) => {
+                write!(f, "    {} {} :: {}", prefix, name, desc.header)
+            }
+            None => {
+                write!(f, "    {} {}", prefix, name)
+            }
+        }
+    }
+}
+
+impl KerblamTomlOptions {
+    /// Try to parse a TOML into a KerblamTomlOptions
+    ///
+    /// The TOML is first validated against the known schema, failing with
+    /// a list of all issues (e.g. unknown keys) if there are any.
+    ///
+    /// This also takes care of warning about the version mismatch of the TOML,
+    /// if needed.
+    pub fn try_from_file(toml_file: impl AsRef<Path>) -> Result<Self> {
+        let layers = ConfigLayers::from_files(&[toml_file.as_ref().to_path_buf()])?;
+        Self::try_from_layers(&layers)
+    }
+
+    /// Build a KerblamTomlOptions from the merged content of several files
+    ///
+    /// This also takes care of warning about the version mismatch of the TOML,
+    /// if needed.
+    pub fn try_from_layers(layers: &ConfigLayers) -> Result<Self> {
+        let config: KerblamTomlOptions = Value::Table(layers.merged.clone()).try_into()?;
+        let config = config.interpolate()?;
+
+        warn_kerblam_version(&config)?;
+        warn_pending_migrations(&layers.merged);
+
+        Ok(config)
+    }
+
+    /// Expand environment variables and `~` in the path-like fields
+    ///
+    /// This touches the `[data.paths]`, `[code]` and `[data.remote]` values,
+    /// the `paths` of the data profiles and the `[execution].workdir`. See `interpolation::interpolate` for the
+    /// supported syntax.
+    fn interpolate(mut self) -> Result<Self> {
+        let expand = |path: Option<PathBuf>, field: &str| -> Result<Option<PathBuf>> {
+            path.map(|x| interpolate_path(&x, field)).transpose()
+        };
+
+        if let Some(data) = self.data.as_mut() {
+            if let Some(paths) = data.paths.as_mut() {
+                paths.input = expand(paths.input.take(), "data.paths.input")?;
+                paths.output = expand(paths.output.take(), "data.paths.output")?;
+                paths.intermediate = expand(paths.intermediate.take(), "data.paths.intermediate")?;
+            }
+            if let Some(profiles) = data.profiles.as_mut() {
+                for (name, profile) in profiles.iter_mut() {
+                    if let Some(redirects) = profile.redirects.as_mut() {
+                        let field = format!("data.profiles.{}.paths", name);
+                        redirects.output =
+                            expand(redirects.output.take(), &format!("{}.output", field))?;
+                        redirects.intermediate = expand(
+                            redirects.intermediate.take(),
+                            &format!("{}.intermediate", field),
+                        )?;
+                    }
+                }
+            }
+            if let Some(remote) = data.remote.take() {
+                let mut expanded = HashMap::with_capacity(remote.len());
+                for (url, path) in remote {
+                    let path = interpolate(&path).with_context(|| {
+                        format!("Failed to expand the value of {:?} in [data.remote]", url)
+                    })?;
+                    expanded.insert(url, path);
+                }
+                data.remote = Some(expanded);
+            }
+        }
+
+        if let Some(code) = self.code.as_mut() {
+            code.env_dir = expand(code.env_dir.take(), "code.env_dir")?;
+            code.pipes_dir = expand(code.pipes_dir.take(), "code.pipes_dir")?;
+        }
+
+        self.execution.workdir = expand(self.execution.workdir.take(), "execution.workdir")?;
+
+        Ok(self)
+    }
+
+    /// Return all paths representing remote files specified in the config
+    ///
+    /// This includes **all** the files, including those not yet downloaded.
+    pub fn remote_files(&self) -> Vec<RemoteFile> {
+        let root_data_dir = self.input_data_dir();
+        log::debug!("Remote file save dir is {root_data_dir:?}");
+
+        self.data
+            .clone()
+            .and_then(|x| x.remote)
+            .map(|y| {
+                y.iter()
+                    // The URLs are validated when the config is loaded,
+                    // so this unwrap should be safe.
+                    .map(|(a, b)| {
+                        RemoteFile::from_string_pair(
+                            (a.as_str(), b.as_str()),
+                            self.input_data_dir(),
+                        )
+                        .unwrap()
+                    })
+                    .collect()
+            })
+            .unwrap_or_default() // The default vec is just empty
+    }
+
+    /// Return the resolved value of one of the `SETTINGS`, by dotted key
+    ///
+    /// Paths are shown relative to the project root, when possible.
+    /// Returns None if the setting is unknown or has no value.
+    pub fn setting(&self, key: &str) -> Option<String> {
+        let here = current_dir().unwrap();
+        let relative = |path: PathBuf| -> Option<String> {
+            let path = path.strip_prefix(&here).unwrap_or(&path).to_path_buf();
+            Some(path.to_string_lossy().to_string())
+        };
+
+        match key {
+            "meta.version" => self.meta.clone().and_then(|x| x.version),
+            "meta.requires" => self.meta.clone().and_then(|x| x.requires),
+            "data.paths.input" => relative(self.input_data_dir()),
+            "data.paths.output" => relative(self.output_data_dir()),
+            "data.paths.intermediate" => relative(self.intermediate_data_dir()),
+            "code.pipes_dir" => relative(self.pipes_dir()),
+            "code.env_dir" => relative(self.env_dir()),
+            "execution.backend" => Some(self.execution.backend.clone().into()),
+            "execution.workdir" => Some(
+                self.execution
+                    .workdir
+                    .clone()
+                    .unwrap_or(PathBuf::from(DEFAULT_WORKDIR))
+                    .to_string_lossy()
+                    .to_string(),
+            ),
+            "execution.offline" => Some(self.execution.offline.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Return the path of the input data directory
+    pub fn input_data_dir(&self) -> PathBuf {
+        current_dir().unwrap().join(
+            self.data
+                .clone()
+                .and_then(|x| x.paths)
+                .and_then(|x| x.input)
+                .unwrap_or(PathBuf::from(DEFAULT_INPUT_DIR)),
+        )
+    }
+
+    /// Return the path of the output data directory
+    pub fn output_data_dir(&self) -> PathBuf {
+        current_dir().unwrap().join(
+            self.data
+                .clone()
+                .and_then(|x| x.paths)
+                .and_then(|x| x.output)
+                .unwrap_or(PathBuf::from(DEFAULT_OUTPUT_DIR)),
+        )
+    }
+
+    /// Return the path of the intermediate data directory
+    pub fn intermediate_data_dir(&self) -> PathBuf {
+        current_dir().unwrap().join(
+            self.data
+                .clone()
+                .and_then(|x| x.paths)
+                .and_then(|x| x.intermediate)
+                .unwrap_or(PathBuf::from(DEFAULT_INTERMEDIATE_DIR)),
+        )
+    }
+
+    /// Return the data directories inside `dir`, other than `dir` itself
+    pub fn data_dirs_inside(&self, dir: &Path) -> Vec<PathBuf> {
+        [
+            self.input_data_dir(),
+            self.output_data_dir(),
+            self.intermediate_data_dir(),
+        ]
+        .into_iter()
+        .filter(|x| x != dir && x.starts_with(dir))
+        .collect()
+    }
+
+    /// Extract the content of input/output/intermediate directories
+    ///
+    /// The `target` is a folder.
+    fn extract_dir(&self, target: PathBuf) -> Vec<PathBuf> {
+        let filter_dirs = vec![
+            self.input_data_dir(),
+            self.intermediate_data_dir(),
+            self.output_data_dir(),
+        ];
+
+        // Remove the parent filter dirs, otherwise we can't look into here
+        let filter_dirs: Vec<PathBuf> = filter_dirs
+            .into_iter()
+            .filter(|path| !target.starts_with(path))
+            .collect();
+
+        log::debug!("Extracting {target:?} with filters {filter_dirs:?}");
+
+        find_files(target, (!filter_dirs.is_empty()).then_some(filter_dirs))
+    }
+
+    /// Return all the locally present input files
+    ///
+    /// This filters out output, temporary and intermediate files from the call
+    pub fn input_files(&self) -> Vec<PathBuf> {
+        self.extract_dir(self.input_data_dir())
+    }
+    /// Return all the locally present output files
+    ///
+    /// This filters out input, temporary and intermediate files from the call
+    pub fn output_files(&self) -> Vec<PathBuf> {
+        self.extract_dir(self.output_data_dir())
+    }
+
+    /// Return all the locally present intermediate files
+    ///
+    /// This filters out output, temporary and input files from the call
+    pub fn intermediate_files(&self) -> Vec<PathBuf> {
+        self.extract_dir(self.intermediate_data_dir())
+    }
+
+    /// Return all locally-present remote files as defined in the config
+    pub fn downloaded_files(&self) -> Vec<RemoteFile> {
+        let locals = self.input_files();
+        self.remote_files()
+            .into_iter()
+            .filter(|remote| locals.iter().any(|x| x.ends_with(&remote.path)))
+            .collect()
+    }
+
+    /// Return all remote files that are not found locally
+    pub fn undownloaded_files(&self) -> Vec<RemoteFile> {
+        let locals = self.input_files();
+        self.remote_files()
+            .into_iter()
+            .filter(|remote| !locals.iter().any(|x| x.ends_with(&remote.path)))
+            .collect()
+    }
+
+    /// Return all files that are deemed 'volatile'
+    ///
+    /// Volatile files are output files, remote files and intermediate files.
+    pub fn volatile_files(&self, output: bool, remote: bool) -> Vec<PathBuf> {
+        let mut files = self.intermediate_files();
+
+        if output {
+            files.extend(self.output_files());
+        }
+        if remote {
+            files.extend(
+                self.downloaded_files()
+                    .into_iter()
+                    .map(|x| x.path)
+                    .collect::<Vec<PathBuf>>(),
+            );
+        }
+
+        files
+            .into_iter()
+            // Get rid of hidden files - we ignore them like a good little program should.
+            .filter(|x| !x.file_name().unwrap().to_string_lossy().starts_with('.'))
+            .collect()
+    }
+
+    /// Return all files that are deemed 'precious'
+    ///
+    /// Precious files are input files that cannot be fetched remotely.
+    pub fn precious_files(&self) -> Vec<PathBuf> {
+        let remote_files = self.remote_files();
+        self.input_files()
+            .into_iter()
+            .filter(|x| !remote_files.iter().any(|y| x == &y.path))
+            .collect()
+    }
+
+    /// Return all pipes
+    ///
+    /// The container of a pipe is the one named in its `@env` metadata, or
+    /// else the one with the same name as the pipe, or else the default one.
+    /// Pipes in subfolders of the pipes dir are namespaced by the folder,
+    /// e.g. `qc/run.sh` is the `qc/run` pipe. Their containers are looked
+    /// up in the same way in the containers dir (e.g. `qc/run.dockerfile`).
+    /// Containers can also be `.image` files, that refer to an existing
+    /// image, and an `image` set for the pipe in the `[pipes]` section
+    /// replaces the container file altogether.
+    ///
+    /// Fails if more than one file gives the same pipe name. Problems with
+    /// a single pipe (e.g. invalid metadata) only make that pipe invalid
+    /// (see `Pipe::check`), so that the others can still be listed and run.
+    pub fn pipes(&self) -> Result<Vec<Pipe>> {
+        let pipes_names: Vec<(String, PathBuf)> = self
+            .pipes_paths()
+            .into_iter()
+            .filter(|x| self.is_pipe(x))
+            .map(|x| (namespaced_name(&self.pipes_dir(), &x), x))
+            .collect();
+        let envs_names = self.containers();
+
+        let mut files_by_name: HashMap<&String, Vec<&PathBuf>> = HashMap::new();
+        for (name, path) in &pipes_names {
+            files_by_name.entry(name).or_default().push(path);
+        }
+        let mut duplicates: Vec<String> = files_by_name
+            .into_iter()
+            .filter(|(_, paths)| paths.len() > 1)
+            .map(|(name, paths)| {
+                let mut paths: Vec<String> = paths
+                    .iter()
+                    .map(|x| x.to_string_lossy().to_string())
+                    .collect();
+                paths.sort_unstable();
+                format!("\t- '{}': {}", name, paths.join(", "))
+            })
+            .collect();
+        if !duplicates.is_empty() {
+            duplicates.sort_unstable();
+            bail!(
+                "Some pipes have the same name. Rename one of the files for each of them:\n{}",
+                duplicates.join("\n")
+            )
+        }
+
+        let default_dockerfile: Option<PathBuf> = envs_names.get("default").cloned();
+
+        let mut pipes: Vec<Pipe> = vec![];
+        for (name, pipe_path) in pipes_names {
+            let resolved = || -> Result<(PipeMetadata, Option<PathBuf>)> {
+                let mut metadata = Pipe::read_metadata(&pipe_path)?;
+                self.add_configured_metadata(&name, &mut metadata)?;
+                let env_path = match &metadata.env {
+                    Some(env) => match envs_names.get(env) {
+                        Some(path) => Some(path.clone()),
+                        None => bail!(
+                            "It asks for the '{}' container, but there is no container file for it in {}",
+                            env,
+                            self.env_dir().to_string_lossy()
+                        ),
+                    },
+                    None => envs_names
+                        .get(&name)
+                        .cloned()
+                        .or(default_dockerfile.clone()),
+                };
+                // An image set in the kerblam.toml replaces the container file
+                let env_path = match metadata.image {
+                    Some(_) => None,
+                    None => {
+                        metadata.image = image_file_reference(env_path.as_ref())?;
+                        env_path
+                    }
+                };
+                Ok((metadata, env_path))
+            };
+            let (metadata, env_path, invalid) = match resolved() {
+                Ok((metadata, env_path)) => (metadata, env_path, None),
+                Err(e) => (PipeMetadata::default(), None, Some(format!("{:#}", e))),
+            };
+            pipes.push(Pipe {
+                name,
+                pipe_path,
+                env_path,
+                metadata,
+                invalid,
+            });
+        }
+
+        log::debug!("Found pipes: {pipes:?}");
+
+        Ok(pipes)
+    }
+
+    /// Return the container files, by their namespaced name
+    ///
+    /// Only the files that the backend can build are included. If there are
+    /// many for the same name, the one with the preferred extension is used.
+    fn containers(&self) -> HashMap<String, PathBuf> {
+        let extensions = self.execution.backend.container_extensions();
+        let preference = |path: &PathBuf| {
+            path.extension()
+                .and_then(|x| extensions.iter().position(|ext| x == *ext))
+        };
+
+        let mut paths: Vec<(usize, PathBuf)> = self
+            .env_paths()
+            .into_iter()
+            .filter_map(|x| preference(&x).map(|i| (i, x)))
+            .collect();
+        paths.sort_unstable();
+
+        let mut containers: HashMap<String, PathBuf> = HashMap::new();
+        for (_, path) in paths {
+            containers
+                .entry(namespaced_name(&self.env_dir(), &path))
+                .or_insert(path);
+        }
+
+        containers
+    }
+
+    /// Return the steps of a pipeline, with the pipes that they run
+    ///
+    /// The `local` and `container` options of each step are already applied
+    /// to its pipe. Fails if a step is not valid, so that nothing is run
+    /// if the pipeline cannot run to the end.
+    pub fn pipeline_steps(&self, name: &str) -> Result<Vec<(PipelineStep, Pipe)>> {
+        let pipeline = self
+            .pipelines
+            .get(name)
+            .ok_or(anyhow!("There is no pipeline named '{}'", name))?;
+        if pipeline.steps.is_empty() {
+            bail!("The pipeline '{}' has no steps", name)
+        }
+        let pipes = self.pipes()?;
+        let containers = self.containers();
+
+        let mut steps = vec![];
+        for (i, step) in pipeline.steps.iter().enumerate() {
+            let context = || format!("Invalid step {} of the pipeline '{}'", i + 1, name);
+            let pipe = match pipes.iter().find(|x| x.name == step.pipe) {
+                Some(x) => {
+                    x.check().with_context(context)?;
+                    x.clone()
+                }
+                None => {
+                    return Err(anyhow!("There is no pipe named '{}'", step.pipe))
+                        .with_context(context)
+                }
+            };
+            let pipe = match (&step.container, step.local) {
+                (Some(_), true) => {
+                    return Err(anyhow!("A step cannot be both local and in a container"))
+                        .with_context(context)
+                }
+                (Some(container), false) => match containers.get(container) {
+                    Some(path) => pipe.with_env(path.clone())?,
+                    None => {
+                        return Err(anyhow!(
+                            "There is no container file for '{}' in {}",
+                            container,
+                            self.env_dir().to_string_lossy()
+                        ))
+                        .with_context(context)
+                    }
+                },
+                (None, true) => pipe.drop_env(),
+                (None, false) => pipe,
+            };
+            steps.push((step.clone(), pipe));
+        }
+
+        Ok(steps)
+    }
+
+    /// Add what is set for a pipe in the `[pipes]` section to its metadata
+    ///
+    /// Parameters replace the ones from the docstring with the same name,
+    /// and dependencies are added to the ones in the docstring.
+    fn add_configured_metadata(&self, pipe_name: &str, metadata: &mut PipeMetadata) -> Result<()> {
+        let options = match self.pipe_options.get(pipe_name) {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+        for pipe in &options.depends_on {
+            metadata.add_dependency(pipe);
+        }
+        if let Some(image) = &options.image {
+            check_image_reference(image)
+                .with_context(|| format!("Invalid image for the pipe '{}'", pipe_name))?;
+            metadata.image = Some(image.clone());
+        }
+
+        let params = &options.params;
+        let mut names: Vec<&String> = params.keys().collect();
+        names.sort_unstable();
+        for name in names {
+            let param = params[name]
+                .clone()
+                .into_param(name)
+                .with_context(|| format!("Invalid parameter in [pipes.{}.params]", pipe_name))?;
+            metadata.set_param(param);
+        }
+
+        Ok(())
+    }
+
+    /// Is this file in the pipes dir a pipe?
+    ///
+    /// Kerblam! must know how to run it (see `ExecutionStrategy::detect`).
+    /// Also, files in subfolders that would just be run with one of the
+    /// default interpreters must have a docstring, so that helper modules
+    /// (e.g. `lib/utils.py`) are not taken for pipes.
+    fn is_pipe(&self, path: &Path) -> bool {
+        let interpreters = &self.execution.interpreters;
+        if ExecutionStrategy::detect(path, interpreters).is_err() {
+            return false;
+        }
+        let in_subfolder = path.parent() != Some(self.pipes_dir().as_path());
+        if in_subfolder && ExecutionStrategy::only_by_default(path, interpreters) {
+            return read_docstring(path).is_ok_and(|x| !x.is_empty());
+        }
+
+        true
+    }
+
+    /// Return all paths to pipes.
+    fn pipes_paths(&self) -> Vec<PathBuf> {
+        let pipes = self.pipes_dir();
+        find_files(pipes, None)
+    }
+
+    /// Return the path to the pipes folder
+    pub fn pipes_dir(&self) -> PathBuf {
+        self.code
+            .clone()
+            .and_then(|x| x.pipes_dir)
+            .unwrap_or_else(|| current_dir().unwrap().join(DEFAULT_PIPES_DIR))
+    }
+
+    /// Return the path to the env folder
+    pub fn env_dir(&self) -> PathBuf {
+        self.code
+            .clone()
+            .and_then(|x| x.env_dir)
+            .unwrap_or_else(|| current_dir().unwrap().join(DEFAULT_ENV_DIR))
+    }
+
+    /// Return all paths to environments.
+    fn env_paths(&self) -> Vec<PathBuf> {
+        let env = self.env_dir();
+        find_files(env, None)
+    }
+}
+
+/// Find and parse the kerblam.toml file
+///
+/// This function walks through the filetree to find the kerblam.toml file.
+/// If no kerblam.toml exists, it fails.
+/// If one is found, it parses it and the CDs the project to the location of
+/// the TOML and parses it, merging in the local overrides (see `config_files`).
+pub fn find_and_parse_kerblam_toml() -> Result<KerblamTomlOptions> {
+    let layers = find_and_load_config_layers()?;
+
+    KerblamTomlOptions::try_from_layers(&layers)
+}
+
+/// Find the kerblam.toml file and load it, with all its overrides, as layers
+///
+/// Like `find_and_parse_kerblam_toml`, this moves the current working
+/// directory to the location of the kerblam.toml file.
+pub fn find_and_load_config_layers() -> Result<ConfigLayers> {
+    let toml_file = match find_kerblam_toml() {
+        // If we find a toml file, move the current working directory there.
+        Some(path) => {
+            set_current_dir(path.parent().unwrap())?;
+            path
+        }
+        None => {
+            bail!(
+                "Not a kerblam! project (or any of the parent directories): no kerblam.toml found."
+            );
+        }
+    };
+
+    let here = &current_dir().unwrap();
+
+    log::debug!("Kerblam is starting in {:?}", here);
+
+    ConfigLayers::from_files(&config_files(toml_file)?)
+}
+
+/// Find the kerblam.toml file in the working directory tree
+///
+/// This function starts from the current working directory and
+/// works its way up to find a kerblam.toml file, returning its
+/// path if it finds one, or None otherwise.
+pub fn find_kerblam_toml() -> Option<PathBuf> {
+    let here = current_dir().unwrap();
+
+    for piece in here.ancestors() {
+        let mut test_path = piece.to_owned().to_path_buf();
+        test_path.push("kerblam.toml");
+        if test_path.exists() {
+            return Some(test_path);
+        }
+    }
+
+    None
+}
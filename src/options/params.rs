@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+
+/// The type of the value of a pipe parameter
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ParamType {
+    #[default]
+    String,
+    Int,
+    Float,
+    Bool,
+}
+
+impl ParamType {
+    /// Check that a value is valid for this type
+    fn check(&self, value: &str) -> Result<()> {
+        let valid = match self {
+            Self::String => true,
+            Self::Int => value.parse::<i64>().is_ok(),
+            Self::Float => value.parse::<f64>().is_ok(),
+            Self::Bool => value == "true" || value == "false",
+        };
+        if !valid {
+            bail!("{:?} is not a valid {}", value, self)
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for ParamType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::String => "string",
+            Self::Int => "int",
+            Self::Float => "float",
+            Self::Bool => "bool",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl TryFrom<&str> for ParamType {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self> {
+        match value {
+            "string" => Ok(Self::String),
+            "int" => Ok(Self::Int),
+            "float" => Ok(Self::Float),
+            "bool" => Ok(Self::Bool),
+            _ => bail!(
+                "Unknown parameter type {:?}. Expected one of: string, int, float, bool",
+                value
+            ),
+        }
+    }
+}
+
+/// A named parameter that a pipe accepts
+#[derive(Debug, Clone, PartialEq)]
+pub struct PipeParam {
+    pub name: String,
+    pub kind: ParamType,
+    /// The value used if the parameter is not set. If None, the parameter
+    /// must always be set.
+    pub default: Option<String>,
+    pub help: Option<String>,
+}
+
+impl PipeParam {
+    fn new(
+        name: &str,
+        kind: ParamType,
+        default: Option<String>,
+        help: Option<String>,
+    ) -> Result<Self> {
+        let valid_name = name.chars().next().is_some_and(|x| !x.is_ascii_digit())
+            && name.chars().all(|x| x.is_ascii_alphanumeric() || x == '_');
+        if !valid_name {
+            bail!(
+                "Invalid parameter name {:?}: use only letters, numbers and underscores",
+                name
+            )
+        }
+        if let Some(default) = &default {
+            kind.check(default)
+                .map_err(|e| anyhow!("Invalid default for parameter '{}': {}", name, e))?;
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            kind,
+            default,
+            help,
+        })
+    }
+
+    /// Parse a parameter from the value of its `@param` docstring line
+    ///
+    /// The format is `name: type = default -- help`, where everything but
+    /// the name is optional. The type defaults to `string`.
+    pub fn from_header(text: &str) -> Result<Self> {
+        let (text, help) = match text.split_once("--") {
+            Some((text, help)) => (text, Some(help.trim().to_string())),
+            None => (text, None),
+        };
+        let (text, default) = match text.split_once('=') {
+            Some((text, default)) => (text, Some(default.trim().to_string())),
+            None => (text, None),
+        };
+        let (name, kind) = match text.split_once(':') {
+            Some((name, kind)) => (name.trim(), ParamType::try_from(kind.trim())?),
+            None => (text.trim(), ParamType::default()),
+        };
+
+        Self::new(name, kind, default, help.filter(|x| !x.is_empty()))
+    }
+}
+
+impl Display for PipeParam {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut details = vec![self.kind.to_string()];
+        match &self.default {
+            Some(default) => details.push(format!("default `{}`", default)),
+            None => details.push("required".to_string()),
+        }
+        write!(f, "`{}` ({})", self.name, details.join(", "))?;
+        if let Some(help) = &self.help {
+            write!(f, ": {}", help)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A pipe parameter, as declared in the `[pipes.<pipe>.params]` section of
+/// the kerblam.toml file.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ParamOptions {
+    #[serde(rename = "type", default)]
+    pub kind: ParamType,
+    pub default: Option<toml::Value>,
+    pub help: Option<String>,
+}
+
+impl ParamOptions {
+    pub fn into_param(self, name: &str) -> Result<PipeParam> {
+        let default = self.default.map(|x| match x {
+            toml::Value::String(x) => x,
+            other => other.to_string(),
+        });
+
+        PipeParam::new(name, self.kind, default, self.help)
+    }
+}
+
+/// Resolve the values of the parameters of a pipe
+///
+/// The `overrides` are `name=value` strings given by the user. Every value
+/// is checked against the type of its parameter, and all parameters without
+/// a default must be set.
+/// Returns the `(name, value)` pairs of all parameters, in declaration order.
+pub fn resolve_params(params: &[PipeParam], overrides: &[String]) -> Result<Vec<(String, String)>> {
+    let mut values: HashMap<&str, &str> = HashMap::new();
+    for item in overrides {
+        let (name, value) = item
+            .split_once('=')
+            .ok_or(anyhow!("Invalid parameter {:?}: use `name=value`", item))?;
+        let param = match params.iter().find(|x| x.name == name.trim()) {
+            Some(x) => x,
+            None if params.is_empty() => {
+                bail!("Unknown parameter '{}': this pipe has no parameters", name)
+            }
+            None => bail!(
+                "Unknown parameter '{}'. The parameters of this pipe are: {}",
+                name,
+                params
+                    .iter()
+                    .map(|x| x.name.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(", ")
+            ),
+        };
+        param
+            .kind
+            .check(value)
+            .map_err(|e| anyhow!("Invalid value for parameter '{}': {}", param.name, e))?;
+        values.insert(&param.name, value);
+    }
+
+    let mut resolved = vec![];
+    for param in params {
+        let value = match (values.get(param.name.as_str()), &param.default) {
+            (Some(value), _) => value.to_string(),
+            (None, Some(default)) => default.clone(),
+            (None, None) => bail!(
+                "The parameter '{}' is required. Set it with `--set {}=<value>`",
+                param.name,
+                param.name
+            ),
+        };
+        resolved.push((param.name.clone(), value));
+    }
+
+    Ok(resolved)
+}
+
+#[test]
+fn test_params() {
+    let threads = PipeParam::from_header("threads: int = 4 -- How many threads to use").unwrap();
+    assert_eq!(
+        threads,
+        PipeParam {
+            name: "threads".into(),
+            kind: ParamType::Int,
+            default: Some("4".into()),
+            help: Some("How many threads to use".into())
+        }
+    );
+    let genome = PipeParam::from_header("genome").unwrap();
+    assert_eq!(genome.kind, ParamType::String);
+    assert!(PipeParam::from_header("threads: int = many").is_err());
+    assert!(PipeParam::from_header("threads: integer").is_err());
+    assert!(PipeParam::from_header("bad-name").is_err());
+
+    let params = vec![threads, genome];
+    let resolve = |x: &[&str]| {
+        resolve_params(
+            &params,
+            &x.iter().map(|x| x.to_string()).collect::<Vec<String>>(),
+        )
+    };
+    assert_eq!(
+        resolve(&["genome=hg38"]).unwrap(),
+        vec![
+            ("threads".to_string(), "4".to_string()),
+            ("genome".to_string(), "hg38".to_string())
+        ]
+    );
+    assert_eq!(resolve(&["genome=a", "threads=8"]).unwrap()[0].1, "8");
+    assert!(resolve(&[]).is_err());
+    assert!(resolve(&["genome=a", "threads=eight"]).is_err());
+    assert!(resolve(&["genome=a", "colour=red"]).is_err());
+}
@@ -0,0 +1,281 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use regex::Regex;
+use walkdir::WalkDir;
+
+/// A wildcard in a glob pattern
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Wildcard {
+    /// `*`, any number of characters in a single path segment
+    Star,
+    /// `?`, exactly one character in a single path segment
+    Question,
+    /// `**`, any number of characters, across path segments
+    DoubleStar,
+}
+
+/// A glob pattern on paths, like `samples/*.fastq.gz`.
+///
+/// Supports `*` and `?`, that match within a single path segment, and `**`
+/// that matches across segments (`**/` also matches no segments at all).
+#[derive(Debug)]
+pub struct Glob {
+    regex: Regex,
+    wildcards: Vec<Wildcard>,
+    /// The literal pieces of the pattern, around the wildcards
+    pieces: Vec<String>,
+}
+
+/// Does this path contain glob wildcards?
+pub fn is_glob(path: &Path) -> bool {
+    path.to_string_lossy().contains(['*', '?'])
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Result<Self> {
+        let mut wildcards = vec![];
+        let mut pieces = vec![String::new()];
+        let mut regex = String::from("^");
+
+        let mut chars = pattern.chars().peekable();
+        while let Some(char) = chars.next() {
+            let (wildcard, expression) = match char {
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    if chars.peek() == Some(&'/') {
+                        chars.next();
+                        (Wildcard::DoubleStar, "((?:.*/)?)")
+                    } else {
+                        (Wildcard::DoubleStar, "(.*)")
+                    }
+                }
+                '*' => (Wildcard::Star, "([^/]*)"),
+                '?' => (Wildcard::Question, "([^/])"),
+                _ => {
+                    pieces.last_mut().unwrap().push(char);
+                    regex.push_str(&regex::escape(&char.to_string()));
+                    continue;
+                }
+            };
+            wildcards.push(wildcard);
+            pieces.push(String::new());
+            regex.push_str(expression);
+        }
+        regex.push('$');
+
+        Ok(Glob {
+            regex: Regex::new(&regex)?,
+            wildcards,
+            pieces,
+        })
+    }
+
+    /// Match a path, returning what each wildcard matched
+    fn captures(&self, path: &str) -> Option<Vec<String>> {
+        let captures = self.regex.captures(path)?;
+        Some(
+            captures
+                .iter()
+                .skip(1)
+                .map(|x| x.map(|x| x.as_str().to_string()).unwrap_or_default())
+                .collect(),
+        )
+    }
+
+    /// Replace each wildcard with the corresponding value
+    fn fill(&self, values: &[String]) -> String {
+        let mut result = self.pieces[0].clone();
+        for (value, piece) in values.iter().zip(self.pieces.iter().skip(1)) {
+            result.push_str(value);
+            result.push_str(piece);
+        }
+        result
+    }
+}
+
+/// Keep only the paths that are not inside another of the paths
+///
+/// Moving a directory also moves everything in it, so nested matches
+/// must be dropped.
+fn outermost(paths: BTreeSet<String>) -> BTreeSet<String> {
+    let mut kept: BTreeSet<String> = BTreeSet::new();
+    // Parents sort before their children, so we always see them first
+    for path in paths {
+        let is_nested = kept
+            .iter()
+            .any(|parent| path.starts_with(&format!("{}/", parent)));
+        if !is_nested {
+            kept.insert(path);
+        }
+    }
+    kept
+}
+
+/// List all files and directories in a directory, relative to it
+pub fn list_candidates(root: &Path) -> Vec<String> {
+    WalkDir::new(root)
+        .min_depth(1)
+        .into_iter()
+        .filter_map(|x| x.ok())
+        .filter_map(|x| {
+            x.path()
+                .strip_prefix(root)
+                .ok()
+                .map(|x| x.to_string_lossy().to_string())
+        })
+        .collect()
+}
+
+/// Expand a pair of glob patterns to the concrete paths they refer to.
+///
+/// Each candidate that matches `origin` is paired with the path obtained by
+/// filling the wildcards of `target` with what they matched in the origin.
+/// Paths that match the `target` pattern are never considered origins.
+/// Fails if the paths matched by `target` are not exactly the ones that
+/// are needed by the origins.
+///
+/// If there is no `target` (e.g. the file is hidden), the origins are paired
+/// with None.
+pub fn expand_glob_pair(
+    origin: &Path,
+    target: Option<&Path>,
+    candidates: &[String],
+) -> Result<Vec<(PathBuf, Option<PathBuf>)>> {
+    let origin_pattern = origin.to_string_lossy();
+    let origin_glob = Glob::new(&origin_pattern)?;
+    let target_glob = match target {
+        Some(x) => Some(Glob::new(&x.to_string_lossy())?),
+        None => None,
+    };
+
+    if let Some(target_glob) = &target_glob {
+        if target_glob.wildcards != origin_glob.wildcards {
+            bail!(
+                "The profile patterns {:?} and {:?} must have the same wildcards, in the same order.",
+                origin,
+                target.unwrap()
+            )
+        }
+    }
+
+    let existing_targets: BTreeSet<String> = match &target_glob {
+        Some(glob) => outermost(
+            candidates
+                .iter()
+                .filter(|x| glob.captures(x).is_some())
+                .cloned()
+                .collect(),
+        ),
+        None => BTreeSet::new(),
+    };
+    let origins: BTreeSet<String> = outermost(
+        candidates
+            .iter()
+            .filter(|x| !existing_targets.contains(*x))
+            .filter(|x| origin_glob.captures(x).is_some())
+            .cloned()
+            .collect(),
+    );
+
+    if origins.is_empty() {
+        bail!("The profile pattern {:?} matches no files.", origin)
+    }
+
+    let target_glob = match target_glob {
+        Some(x) => x,
+        None => return Ok(origins.into_iter().map(|x| (x.into(), None)).collect()),
+    };
+
+    let pairs: Vec<(String, String)> = origins
+        .into_iter()
+        .map(|x| {
+            let target = target_glob.fill(&origin_glob.captures(&x).unwrap());
+            (x, target)
+        })
+        .collect();
+
+    let needed_targets: BTreeSet<String> = pairs.iter().map(|(_, x)| x.clone()).collect();
+    let missing: Vec<&String> = needed_targets.difference(&existing_targets).collect();
+    let unused: Vec<&String> = existing_targets.difference(&needed_targets).collect();
+    if !missing.is_empty() || !unused.is_empty() {
+        let mut message = format!(
+            "The profile patterns {:?} and {:?} match different sets of files.",
+            origin,
+            target.unwrap()
+        );
+        for path in missing {
+            message.push_str(&format!("\n\t- {} is needed, but does not exist", path));
+        }
+        for path in unused {
+            message.push_str(&format!("\n\t- {} exists, but has no original", path));
+        }
+        bail!(message)
+    }
+
+    Ok(pairs
+        .into_iter()
+        .map(|(origin, target)| (origin.into(), Some(target.into())))
+        .collect())
+}
+
+#[test]
+fn test_expand_glob_pair() {
+    let candidates: Vec<String> = [
+        "samples",
+        "samples/a.fastq.gz",
+        "samples/b.fastq.gz",
+        "samples/notes.txt",
+        "test_samples",
+        "test_samples/a.fastq.gz",
+        "test_samples/b.fastq.gz",
+        "nested/x/data.csv",
+        "nested/y/data.csv",
+        "nested/x/test_data.csv",
+        "nested/y/test_data.csv",
+    ]
+    .iter()
+    .map(|x| x.to_string())
+    .collect();
+    let expand = |origin: &str, target: Option<&str>| {
+        expand_glob_pair(Path::new(origin), target.map(Path::new), &candidates)
+    };
+
+    let pairs = expand("samples/*.fastq.gz", Some("test_samples/*.fastq.gz")).unwrap();
+    assert_eq!(
+        pairs,
+        vec![
+            (
+                "samples/a.fastq.gz".into(),
+                Some("test_samples/a.fastq.gz".into())
+            ),
+            (
+                "samples/b.fastq.gz".into(),
+                Some("test_samples/b.fastq.gz".into())
+            ),
+        ]
+    );
+
+    // '**' spans directories, and targets are not mistaken for origins
+    let pairs = expand("**/*data.csv", Some("**/test_*data.csv")).unwrap();
+    assert_eq!(pairs.len(), 2);
+    assert_eq!(
+        pairs[0],
+        (
+            "nested/x/data.csv".into(),
+            Some("nested/x/test_data.csv".into())
+        )
+    );
+
+    // Directories are candidates too
+    let pairs = expand("sample?", None).unwrap();
+    assert_eq!(pairs, vec![("samples".into(), None)]);
+
+    let error = expand("samples/*", Some("test_samples/*")).unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("test_samples/notes.txt is needed"));
+    assert!(expand("samples/*.bam", Some("test_samples/*.bam")).is_err());
+    assert!(expand("samples/*", Some("test_samples/?")).is_err());
+}
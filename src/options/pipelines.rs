@@ -0,0 +1,64 @@
+use std::fmt::Display;
+
+use serde::Deserialize;
+
+/// A named sequence of pipes, in the `[pipelines]` section of the
+/// kerblam.toml file. The pipes are run in order, as a unit.
+#[derive(Debug, Deserialize, Clone)]
+pub struct PipelineOptions {
+    pub description: Option<String>,
+    pub steps: Vec<PipelineStep>,
+}
+
+/// One step of a pipeline: a pipe, and how to run it
+#[derive(Debug, Deserialize, Clone)]
+pub struct PipelineStep {
+    /// The name of the pipe to run
+    pub pipe: String,
+    /// The data profile to use, instead of the one given to `kerblam run`
+    pub profile: Option<String>,
+    /// Run the pipe without a container, even if it has one
+    #[serde(default)]
+    pub local: bool,
+    /// The container to use (e.g. `alignment` for `alignment.dockerfile`),
+    /// instead of the one of the pipe
+    pub container: Option<String>,
+}
+
+impl PipelineStep {
+    /// The name of the pipe, followed by the options of this step, if any
+    pub fn label(&self) -> String {
+        let mut options: Vec<String> = vec![];
+        if let Some(profile) = &self.profile {
+            options.push(format!("profile: {}", profile));
+        }
+        if self.local {
+            options.push("local".to_string());
+        }
+        if let Some(container) = &self.container {
+            options.push(format!("container: {}", container));
+        }
+
+        if options.is_empty() {
+            self.pipe.clone()
+        } else {
+            format!("{} ({})", self.pipe, options.join(", "))
+        }
+    }
+}
+
+impl Display for PipelineOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(description) = &self.description {
+            writeln!(f, "{}\n", description)?;
+        }
+        let steps: Vec<String> = self
+            .steps
+            .iter()
+            .enumerate()
+            .map(|(i, step)| format!("{}. {}", i + 1, step.label()))
+            .collect();
+
+        write!(f, "{}", steps.join("\n"))
+    }
+}
@@ -0,0 +1,675 @@
+use std::collections::{HashMap, HashSet};
+use std::env::current_dir;
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+
+use super::inference::{default_rules, infer_pairs, InferenceRule, InferredPair};
+use super::patterns::{expand_glob_pair, is_glob, list_candidates};
+use super::KerblamTomlOptions;
+use crate::execution::FileMover;
+use crate::utils::{get_salt, kerblam_create_dir, push_fragment};
+
+/// How the files of a profile are put in place during a run
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ProfileMode {
+    /// Move the original files away, and the profile files in their place
+    #[default]
+    Move,
+    /// Leave the original files alone, and expose the profile files through
+    /// an overlay of symlinks
+    Symlink,
+}
+
+/// Data directories that a profile redirects elsewhere during a run
+///
+/// The paths are relative to the project root, like the ones in `[data.paths]`.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ProfileRedirects {
+    pub output: Option<PathBuf>,
+    pub intermediate: Option<PathBuf>,
+}
+
+/// The options of a single data profile, as written in the kerblam.toml file.
+///
+/// Apart from the special keys below, all keys are `original = "target"`
+/// pairs of paths, relative to the input data directory.
+/// Files with the same name as a special key go in the `files` sub-table.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ProfileOptions {
+    /// The name of a profile to inherit the paths from
+    pub extends: Option<String>,
+    /// Paths inherited from the parent profile that should be dropped
+    #[serde(default)]
+    pub remove: Vec<PathBuf>,
+    /// How to put the profile files in place, overriding `data.profiles_mode`
+    pub mode: Option<ProfileMode>,
+    /// Where to send the output and intermediate files during the run
+    #[serde(rename = "paths")]
+    pub redirects: Option<ProfileRedirects>,
+    /// Extra environment variables to set during the run
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Arguments to pass to the workflow, before the ones given by the user
+    pub extra_args: Option<Vec<String>>,
+    /// More original: target pairs of paths, that can use any name
+    #[serde(default)]
+    pub files: HashMap<PathBuf, PathBuf>,
+    /// The original: target pairs of paths
+    #[serde(flatten)]
+    pub paths: HashMap<PathBuf, PathBuf>,
+}
+
+impl ProfileOptions {
+    /// Return the special keys that are set in this profile
+    fn special_keys(&self) -> Vec<&'static str> {
+        [
+            ("extends", self.extends.is_some()),
+            ("remove", !self.remove.is_empty()),
+            ("mode", self.mode.is_some()),
+            ("paths", self.redirects.is_some()),
+            ("env", !self.env.is_empty()),
+            ("extra_args", self.extra_args.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(key, set)| set.then_some(key))
+        .collect()
+    }
+}
+
+impl From<HashMap<PathBuf, PathBuf>> for ProfileOptions {
+    fn from(paths: HashMap<PathBuf, PathBuf>) -> Self {
+        Self {
+            paths,
+            ..Default::default()
+        }
+    }
+}
+
+/// Return the chain of `extends` of a profile, from the profile to the root
+///
+/// Fails if a profile in the chain does not exist, or if the chain loops.
+fn profile_chain<'a>(
+    profiles: &'a HashMap<String, ProfileOptions>,
+    name: &'a str,
+) -> Result<Vec<&'a str>> {
+    let mut chain: Vec<&str> = vec![];
+    let mut current = Some(name);
+    while let Some(profile_name) = current {
+        if chain.contains(&profile_name) {
+            chain.push(profile_name);
+            bail!("Profile inheritance cycle: {}", chain.join(" -> "))
+        }
+        let profile = profiles.get(profile_name).ok_or(match chain.last() {
+            None => anyhow!("Could not find {} profile", profile_name),
+            Some(child) => anyhow!(
+                "Could not find {} profile, extended by the {} profile",
+                profile_name,
+                child
+            ),
+        })?;
+        chain.push(profile_name);
+        current = profile.extends.as_deref();
+    }
+
+    Ok(chain)
+}
+
+/// Resolve the paths of a profile, following the chain of `extends`.
+///
+/// Parents are resolved first: the child profile then drops the paths in
+/// its `remove` list and adds (or overrides) its own paths.
+/// Fails if a profile in the chain does not exist, or if the chain loops.
+pub fn resolve_profile(
+    profiles: &HashMap<String, ProfileOptions>,
+    name: &str,
+) -> Result<HashMap<PathBuf, PathBuf>> {
+    let chain = profile_chain(profiles, name)?;
+
+    let mut resolved: HashMap<PathBuf, PathBuf> = HashMap::new();
+    for profile_name in chain.into_iter().rev() {
+        let profile = &profiles[profile_name];
+        for path in &profile.remove {
+            if resolved.remove(path).is_none() {
+                log::warn!(
+                    "Profile {} removes {:?}, but it does not inherit it. Ignoring it.",
+                    profile_name,
+                    path
+                );
+            }
+        }
+        resolved.extend(profile.paths.clone());
+        resolved.extend(profile.files.clone());
+    }
+
+    Ok(resolved)
+}
+
+impl KerblamTomlOptions {
+    /// Return all the profiles, including the inferred 'test' profile
+    ///
+    /// If there is no explicit 'test' profile, one is inferred from the
+    /// input files with the rules in `[data.inference]`, if any.
+    /// This is only done the first time, as it needs to walk the whole input
+    /// dir: later calls on the same config reuse the same profiles, until
+    /// `forget_profiles` is called.
+    pub fn profiles(&self) -> HashMap<String, ProfileOptions> {
+        self.profiles_cache
+            .get_or_init(|| self.find_profiles())
+            .clone()
+    }
+
+    /// Forget the profiles found by `profiles`, so they are found again
+    ///
+    /// Use this when the input files might have changed, e.g. between the
+    /// runs of the steps of a pipeline.
+    pub fn forget_profiles(&mut self) {
+        self.profiles_cache.take();
+    }
+
+    /// Find all the profiles, inferring the 'test' profile (see `profiles`)
+    fn find_profiles(&self) -> HashMap<String, ProfileOptions> {
+        let mut profiles = self
+            .data
+            .clone()
+            .and_then(|x| x.profiles)
+            .unwrap_or_default();
+
+        if !profiles.contains_key("test") {
+            // Make the paths relative to the input dir, like the ones in
+            // the kerblam.toml, so other profiles can extend this one.
+            let input_dir = self.input_data_dir();
+            let relative = |path: PathBuf| -> PathBuf {
+                path.strip_prefix(&input_dir)
+                    .map(|x| x.to_path_buf())
+                    .unwrap_or(path)
+            };
+            let inferred_test: HashMap<PathBuf, PathBuf> = self
+                .inferred_test_pairs()
+                .into_iter()
+                .map(|x| (relative(x.original), relative(x.target)))
+                .collect();
+            if !inferred_test.is_empty() {
+                log::debug!("Inserted inferred test profile: {inferred_test:?}");
+                profiles.insert("test".to_string(), inferred_test.into());
+            }
+        }
+
+        // A special key might have been meant as the name of an input file
+        let input_dir = self.input_data_dir();
+        for (name, profile) in &profiles {
+            for key in profile.special_keys() {
+                if input_dir.join(key).exists() && !profile.files.contains_key(Path::new(key)) {
+                    eprintln!(
+                        "⚠️  The {} profile sets `{}`, that is also the name of the input file {:?}. To profile that file, put it in the [data.profiles.{}.files] table.",
+                        name,
+                        key,
+                        input_dir.join(key),
+                        name
+                    );
+                }
+            }
+        }
+
+        profiles
+    }
+
+    /// Return the rules used to infer the 'test' profile
+    pub fn inference_rules(&self) -> Vec<InferenceRule> {
+        self.data
+            .as_ref()
+            .and_then(|x| x.inference.clone())
+            .map(|x| x.rules)
+            .unwrap_or_else(default_rules)
+    }
+
+    /// Return the (absolute) file pairs of the inferred 'test' profile
+    ///
+    /// These are found even if there is an explicit 'test' profile, that
+    /// would take precedence over them.
+    pub fn inferred_test_pairs(&self) -> Vec<InferredPair> {
+        infer_pairs(
+            &self.inference_rules(),
+            &current_dir().unwrap(),
+            &self.input_data_dir(),
+            &self.input_files(),
+        )
+    }
+
+    /// Return the resolved original: target paths of a profile
+    pub fn resolved_profile(&self, name: &str) -> Result<HashMap<PathBuf, PathBuf>> {
+        resolve_profile(&self.profiles(), name)
+    }
+
+    /// Return the mode of a profile
+    ///
+    /// This is the `mode` of the profile, or of the closest profile that it
+    /// extends with one, or else `data.profiles_mode`.
+    pub fn profile_mode(&self, name: &str) -> Result<ProfileMode> {
+        let profiles = self.profiles();
+        let chain = profile_chain(&profiles, name)?;
+        let mode = chain.iter().find_map(|x| profiles[*x].mode);
+
+        Ok(mode
+            .or(self.data.as_ref().and_then(|x| x.profiles_mode))
+            .unwrap_or_default())
+    }
+
+    /// Return the environment variables that a profile sets
+    ///
+    /// The variables of the profiles that it extends are inherited, and can
+    /// be overridden.
+    pub fn profile_env(&self, name: &str) -> Result<HashMap<String, String>> {
+        let profiles = self.profiles();
+        let chain = profile_chain(&profiles, name)?;
+
+        let mut env = HashMap::new();
+        for profile_name in chain.into_iter().rev() {
+            env.extend(profiles[profile_name].env.clone());
+        }
+
+        Ok(env)
+    }
+
+    /// Return the extra arguments that a profile passes to the workflow
+    ///
+    /// Like the mode, these are taken from the profile, or from the closest
+    /// profile that it extends with some.
+    pub fn profile_extra_args(&self, name: &str) -> Result<Vec<String>> {
+        let profiles = self.profiles();
+        let chain = profile_chain(&profiles, name)?;
+
+        Ok(chain
+            .iter()
+            .find_map(|x| profiles[*x].extra_args.clone())
+            .unwrap_or_default())
+    }
+
+    /// Return the (data dir, redirected dir) pairs of a profile, if any
+    ///
+    /// Like the mode, each redirect is taken from the profile, or from the
+    /// closest profile that it extends with one. Redirects to the data dir
+    /// itself are dropped, as they would do nothing.
+    ///
+    /// Fails if a redirected dir contains the input dir or the other data
+    /// dir (like the default intermediate dir, `data`), as they would be
+    /// moved away with it.
+    pub fn profile_redirects(&self, name: &str) -> Result<Vec<(PathBuf, PathBuf)>> {
+        let profiles = self.profiles();
+        let chain = profile_chain(&profiles, name)?;
+        let redirects: Vec<&ProfileRedirects> = chain
+            .iter()
+            .filter_map(|x| profiles[*x].redirects.as_ref())
+            .collect();
+
+        let root = current_dir()?;
+        let output = redirects.iter().find_map(|x| x.output.clone());
+        let intermediate = redirects.iter().find_map(|x| x.intermediate.clone());
+
+        // The intermediate dir goes first, as by default it contains the
+        // output dir: that way, the output dir is redirected from its new place
+        let mut result = vec![];
+        for (data_dir, target) in [
+            (self.intermediate_data_dir(), intermediate),
+            (self.output_data_dir(), output),
+        ] {
+            let target = match target {
+                Some(x) => root.join(x),
+                None => continue,
+            };
+            if target == data_dir {
+                continue;
+            }
+            let relative = |x: &PathBuf| x.strip_prefix(&root).unwrap_or(x).to_owned();
+            if data_dir.starts_with(&target) {
+                bail!(
+                    "The {} profile redirects {:?} to {:?}, that contains it.",
+                    name,
+                    relative(&data_dir),
+                    relative(&target)
+                )
+            }
+            result.push((data_dir, target));
+        }
+
+        Ok(result)
+    }
+}
+
+/// Describe a resolved profile, one path (or setting) per line, for the user
+pub fn describe_profile(config: &KerblamTomlOptions, name: &str) -> Result<String> {
+    let paths = config.resolved_profile(name)?;
+    let mut paths: Vec<(&PathBuf, &PathBuf)> = paths.iter().collect();
+    paths.sort_unstable();
+
+    let mut lines = vec![format!("📂 Profile '{}':", name)];
+    for (original, target) in paths {
+        let target = target.to_string_lossy();
+        if target == "_" {
+            lines.push(format!("    {} (hidden)", original.to_string_lossy()));
+        } else {
+            lines.push(format!("    {} <- {}", original.to_string_lossy(), target));
+        }
+    }
+    if lines.len() == 1 {
+        lines.push("    (no paths)".to_string());
+    }
+
+    let root = current_dir().unwrap();
+    for (data_dir, target) in config.profile_redirects(name)? {
+        lines.push(format!(
+            "    {} -> {} (redirected)",
+            data_dir
+                .strip_prefix(&root)
+                .unwrap_or(&data_dir)
+                .to_string_lossy(),
+            target
+                .strip_prefix(&root)
+                .unwrap_or(&target)
+                .to_string_lossy()
+        ));
+    }
+
+    let mut env: Vec<(String, String)> = config.profile_env(name)?.into_iter().collect();
+    env.sort_unstable();
+    for (key, value) in env {
+        lines.push(format!("    ${} = {:?}", key, value));
+    }
+
+    let extra_args = config.profile_extra_args(name)?;
+    if !extra_args.is_empty() {
+        lines.push(format!("    extra arguments: {}", extra_args.join(" ")));
+    }
+
+    Ok(lines.join("\n"))
+}
+
+/// Represents a Kerblam! profile, with a series of files to be moved back and forth
+struct Profile<T: Into<PathBuf> + Hash + std::cmp::Eq + Clone + std::fmt::Debug> {
+    /// The origin: target list of paths
+    ///
+    /// 'origin' (the key) is the real file that needs to be substituted,
+    /// 'target' (the value) is the file that will substitute the original one
+    targets: HashMap<T, Option<T>>,
+    /// The root directory where the data lives, e.g. "/../../data/"
+    root_dir: PathBuf,
+    /// The temporary "swap" directory where the data will be (temporarily) moved
+    temp_dir: PathBuf,
+}
+
+impl<T: Into<PathBuf> + Hash + std::cmp::Eq + Clone + std::fmt::Debug> Profile<T> {
+    #[allow(dead_code)]
+    fn add_paths(&mut self, origin: T, target: Option<T>) {
+        self.targets.insert(origin, target);
+    }
+
+    fn into_filemovers(self) -> Vec<FileMover> {
+        log::debug!("Converting hashmap to filemovers: {:?}", self.targets);
+        self.targets
+            .into_iter()
+            .flat_map(|(origin, target)| {
+                // The original will always be moved to the temporary
+                // This also needs to happen FIRST, not later.
+                let original: PathBuf = origin.into();
+                let mut res = vec![
+                    // We always need to move the original to the temporary file
+                    FileMover::from((
+                        &self.root_dir.join(&original),
+                        push_fragment(
+                            self.temp_dir.join(original.file_name().unwrap()),
+                            &format!(".{}", get_salt(5)),
+                        ),
+                    )),
+                ];
+
+                if let Some(t) = target {
+                    // This is a regular target: we also need to move the target to the original's
+                    // position
+                    let target = t.into();
+                    res.push(FileMover::from((
+                        &self.root_dir.join(target),
+                        &self.root_dir.join(&original),
+                    )));
+                }
+
+                res
+            })
+            .collect()
+    }
+
+    fn from(targets: HashMap<T, Option<T>>, root_dir: PathBuf, temp_dir: PathBuf) -> Self {
+        Self {
+            targets,
+            root_dir,
+            temp_dir,
+        }
+    }
+}
+
+/// Return the (original, target) paths of a profile, relative to the input dir
+///
+/// Glob patterns are expanded to the paths they match. Targets are None if
+/// the original should just be hidden (i.e. with the `"_"` target).
+pub fn expand_profile_paths(
+    config: &KerblamTomlOptions,
+    profile_name: &str,
+) -> Result<Vec<(PathBuf, Option<PathBuf>)>> {
+    let root_dir = config.input_data_dir();
+    let profile = config.resolved_profile(profile_name)?;
+
+    // Expand glob patterns, if any, to the paths that they match
+    let candidates = if profile.keys().any(|x| is_glob(x)) {
+        list_candidates(&root_dir)
+    } else {
+        vec![]
+    };
+    let mut expanded: Vec<(PathBuf, Option<PathBuf>)> = vec![];
+    for (x, y) in profile.iter() {
+        let target = if y.to_string_lossy() == "_" {
+            None
+        } else {
+            Some(y.as_path())
+        };
+        if is_glob(x) {
+            expanded.extend(expand_glob_pair(x, target, &candidates)?);
+        } else {
+            expanded.push((x.to_owned(), target.map(|x| x.to_owned())));
+        }
+    }
+
+    let mut seen: HashSet<&PathBuf> = HashSet::new();
+    for (x, _) in &expanded {
+        if !seen.insert(x) {
+            bail!(
+                "The path {:?} is swapped more than once in the {} profile.",
+                x,
+                profile_name
+            )
+        }
+    }
+
+    Ok(expanded)
+}
+
+// TODO: This checks for the existence of profile paths here. This is a bad
+// thing. It's best to handle the error when we actually do the move.
+// This was done this way because I want a nice error list.
+// The 'check_existence' check was added to overcome this, but it's a hack.
+pub fn extract_profile_paths(
+    config: &KerblamTomlOptions,
+    profile_name: &str,
+    check_existance: bool,
+) -> Result<Vec<FileMover>> {
+    let root_dir = config.input_data_dir();
+    let temp_dir = current_dir().unwrap().join(".kerblam/scratch");
+
+    kerblam_create_dir(&temp_dir)?;
+
+    // Check if the scratch directory is empty. If not, we bail early.
+    // If this is not empty, something has gone wrong in a previous run.
+    if temp_dir.read_dir()?.next().is_some() {
+        bail!(
+            "The Kerblam scratch folder ({}) is not empty! Aborting before we do anything stupid. Was a previous run interrupted? Try `kerblam recover`.",
+            temp_dir.to_string_lossy()
+        )
+    }
+
+    let file_movers = profile_file_movers(config, profile_name, temp_dir)?;
+
+    // Check if the sources exist, otherwise we crash now, and not later
+    // when we actually move the files.
+    let origin_exist_check: Vec<String> = missing_sources(&file_movers, &root_dir)
+        .into_iter()
+        .map(|x| format!("\t- {}", x.to_string_lossy()))
+        .collect();
+
+    if check_existance && !origin_exist_check.is_empty() {
+        bail!(
+            "Failed to find some profiles files:\n{}",
+            origin_exist_check.join("\n")
+        )
+    }
+
+    Ok(file_movers)
+}
+
+/// Return the file movers that put a profile in place
+///
+/// The originals are moved to `temp_dir`, and the targets in their place.
+fn profile_file_movers(
+    config: &KerblamTomlOptions,
+    profile_name: &str,
+    temp_dir: PathBuf,
+) -> Result<Vec<FileMover>> {
+    let root_dir = config.input_data_dir();
+    let profile = expand_profile_paths(config, profile_name)?;
+
+    // Expand profile paths
+    let profile: HashMap<PathBuf, Option<PathBuf>> = profile
+        .into_iter()
+        .map(|(x, y)| (root_dir.join(x), y.map(|y| root_dir.join(y))))
+        .collect();
+
+    let profile = Profile::from(profile.to_owned(), root_dir.clone(), temp_dir);
+
+    let file_movers = profile.into_filemovers();
+    log::debug!("Obtained filemovers: {:?}", file_movers);
+
+    Ok(file_movers)
+}
+
+/// Return the sources of the file movers that do not exist
+///
+/// Paths in the `root_dir` are made relative to it.
+fn missing_sources(file_movers: &[FileMover], root_dir: &Path) -> Vec<PathBuf> {
+    file_movers
+        .iter()
+        .map(|mover| mover.get_from())
+        .filter(|origin| !origin.exists())
+        .map(|x| match x.strip_prefix(root_dir) {
+            Ok(stripped) => stripped.to_path_buf(),
+            Err(_) => x,
+        })
+        .collect()
+}
+
+/// Return the paths of a profile that are needed to use it, but do not exist
+///
+/// This makes the same checks as `extract_profile_paths`, without touching
+/// anything on disk.
+pub fn missing_profile_paths(
+    config: &KerblamTomlOptions,
+    profile_name: &str,
+) -> Result<Vec<PathBuf>> {
+    let temp_dir = current_dir()?.join(".kerblam/scratch");
+    let file_movers = profile_file_movers(config, profile_name, temp_dir)?;
+
+    Ok(missing_sources(&file_movers, &config.input_data_dir()))
+}
+
+#[test]
+fn test_profile_inheritance() {
+    let profiles: HashMap<String, ProfileOptions> = toml::from_str(
+        r#"
+[test]
+"a.txt" = "test_a.txt"
+"b.txt" = "test_b.txt"
+
+[test_small]
+extends = "test"
+remove = ["b.txt"]
+"a.txt" = "small_a.txt"
+"c.txt" = "small_c.txt"
+
+[test_small.files]
+env = "small_env"
+
+[loop_a]
+extends = "loop_b"
+
+[loop_b]
+extends = "loop_a"
+"#,
+    )
+    .unwrap();
+
+    let resolved = resolve_profile(&profiles, "test_small").unwrap();
+    assert_eq!(
+        resolved,
+        HashMap::from([
+            (PathBuf::from("a.txt"), PathBuf::from("small_a.txt")),
+            (PathBuf::from("c.txt"), PathBuf::from("small_c.txt")),
+            (PathBuf::from("env"), PathBuf::from("small_env")),
+        ])
+    );
+    assert_eq!(
+        profiles["test_small"].special_keys(),
+        vec!["extends", "remove"]
+    );
+    assert_eq!(resolve_profile(&profiles, "test").unwrap().len(), 2);
+
+    let error = resolve_profile(&profiles, "loop_a")
+        .unwrap_err()
+        .to_string();
+    assert_eq!(
+        error,
+        "Profile inheritance cycle: loop_a -> loop_b -> loop_a"
+    );
+    assert!(resolve_profile(&profiles, "missing").is_err());
+}
+
+#[test]
+fn test_redirects_in_default_layout() {
+    let config: KerblamTomlOptions = toml::from_str(
+        r#"
+[data.profiles.test.paths]
+output = "data/out/test"
+
+[data.profiles.test_tmp.paths]
+output = "data/out/test"
+intermediate = "data/test"
+"#,
+    )
+    .unwrap();
+    let root = current_dir().unwrap();
+
+    assert_eq!(
+        config.profile_redirects("test").unwrap(),
+        vec![(root.join("data/out"), root.join("data/out/test"))]
+    );
+    assert_eq!(
+        config.profile_redirects("test_tmp").unwrap(),
+        vec![
+            (root.join("data"), root.join("data/test")),
+            (root.join("data/out"), root.join("data/out/test"))
+        ]
+    );
+    // The default intermediate dir contains the input and output dirs
+    assert_eq!(
+        config.data_dirs_inside(&root.join("data")),
+        vec![root.join("data/in"), root.join("data/out")]
+    );
+}
@@ -0,0 +1,607 @@
+use std::fmt::Display;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use toml_edit::{ImDocument, Item};
+use url::Url;
+
+/// The shape that a value in the kerblam.toml file is expected to have.
+///
+/// This mirrors the `*Options` structs that we deserialize the TOML into,
+/// but it is kept separate so we can report *all* the problems in a file at
+/// once (with their position), and not just the first one that serde finds.
+pub enum Schema {
+    /// A table with a fixed set of known keys
+    Table(&'static [(&'static str, Schema)]),
+    /// A table with arbitrary keys, all with values of the same shape.
+    ///
+    /// Keys can optionally be checked with `key_check`, which returns an
+    /// error message if the key is not valid.
+    Map {
+        key_check: Option<fn(&str) -> Option<String>>,
+        values: &'static Schema,
+    },
+    /// A string
+    String,
+    /// A string with a command line, like `python3 -u`, that is not blank
+    Command,
+    /// A boolean
+    Bool,
+    /// A string, number or boolean
+    Scalar,
+    /// A string that must be one of a fixed set of values
+    Choice(&'static [&'static str]),
+    /// An array with all values of the same shape
+    Array(&'static Schema),
+    /// A table with some known keys, and arbitrary other keys with values of
+    /// the same shape.
+    ///
+    /// The `hint` is added to the issues of known keys that have a value
+    /// of the shape of the other keys, as they were probably meant as such.
+    Mixed {
+        fields: &'static [(&'static str, Schema)],
+        values: &'static Schema,
+        hint: &'static str,
+    },
+}
+
+impl Schema {
+    /// Find the schema of a nested value, given the keys that lead to it
+    ///
+    /// Returns None if no such value is allowed by this schema.
+    pub fn lookup(&self, keys: &[&str]) -> Option<&Schema> {
+        match keys.split_first() {
+            None => Some(self),
+            Some((first, rest)) => match self {
+                Schema::Table(fields) => fields
+                    .iter()
+                    .find(|(name, _)| name == first)
+                    .and_then(|(_, inner)| inner.lookup(rest)),
+                Schema::Map { values, .. } => values.lookup(rest),
+                Schema::Mixed { fields, values, .. } => {
+                    match fields.iter().find(|(name, _)| name == first) {
+                        Some((_, inner)) => inner.lookup(rest),
+                        None => values.lookup(rest),
+                    }
+                }
+                _ => None,
+            },
+        }
+    }
+
+    /// Does this schema describe a string value?
+    pub fn is_string(&self) -> bool {
+        matches!(self, Schema::String | Schema::Command | Schema::Choice(_))
+    }
+}
+
+/// Check that the key of a `[data.remote]` entry is a valid URL
+///
+/// The special "_" key is also allowed, and means "this file is remote but
+/// Kerblam! should not fetch it".
+fn check_remote_url(key: &str) -> Option<String> {
+    if key == "_" {
+        return None;
+    }
+    match Url::parse(key) {
+        Ok(_) => None,
+        Err(e) => Some(format!("invalid URL {:?} in [data.remote]: {}", key, e)),
+    }
+}
+
+/// The schema of the whole kerblam.toml file
+pub const KERBLAM_TOML_SCHEMA: Schema = Schema::Table(&[
+    (
+        "meta",
+        Schema::Table(&[("version", Schema::String), ("requires", Schema::String)]),
+    ),
+    (
+        "data",
+        Schema::Table(&[
+            (
+                "paths",
+                Schema::Table(&[
+                    ("input", Schema::String),
+                    ("output", Schema::String),
+                    ("intermediate", Schema::String),
+                ]),
+            ),
+            (
+                "profiles",
+                Schema::Map {
+                    key_check: None,
+                    values: &Schema::Mixed {
+                        fields: &[
+                            ("extends", Schema::String),
+                            ("remove", Schema::Array(&Schema::String)),
+                            ("mode", Schema::Choice(&["move", "symlink"])),
+                            (
+                                "paths",
+                                Schema::Table(&[
+                                    ("output", Schema::String),
+                                    ("intermediate", Schema::String),
+                                ]),
+                            ),
+                            (
+                                "env",
+                                Schema::Map {
+                                    key_check: None,
+                                    values: &Schema::String,
+                                },
+                            ),
+                            ("extra_args", Schema::Array(&Schema::String)),
+                            (
+                                "files",
+                                Schema::Map {
+                                    key_check: None,
+                                    values: &Schema::String,
+                                },
+                            ),
+                        ],
+                        values: &Schema::String,
+                        hint: "to profile a file with this name, put it in the `files` table of the profile",
+                    },
+                },
+            ),
+            ("profiles_mode", Schema::Choice(&["move", "symlink"])),
+            (
+                "inference",
+                Schema::Table(&[(
+                    "rules",
+                    Schema::Array(&Schema::Table(&[
+                        ("prefix", Schema::String),
+                        ("suffix", Schema::String),
+                        ("directory", Schema::String),
+                        ("regex", Schema::String),
+                        ("original", Schema::String),
+                    ])),
+                )]),
+            ),
+            (
+                "remote",
+                Schema::Map {
+                    key_check: Some(check_remote_url),
+                    values: &Schema::String,
+                },
+            ),
+        ]),
+    ),
+    (
+        "code",
+        Schema::Table(&[("env_dir", Schema::String), ("pipes_dir", Schema::String)]),
+    ),
+    (
+        "execution",
+        Schema::Table(&[
+            (
+                "backend",
+                Schema::Choice(&["docker", "podman", "apptainer", "singularity"]),
+            ),
+            ("workdir", Schema::String),
+            ("offline", Schema::Bool),
+            (
+                "interpreters",
+                Schema::Map {
+                    key_check: None,
+                    values: &Schema::Command,
+                },
+            ),
+        ]),
+    ),
+    (
+        "pipes",
+        Schema::Map {
+            key_check: None,
+            values: &Schema::Table(&[
+                (
+                    "params",
+                    Schema::Map {
+                        key_check: None,
+                        values: &Schema::Table(&[
+                            ("type", Schema::Choice(&["string", "int", "float", "bool"])),
+                            ("default", Schema::Scalar),
+                            ("help", Schema::String),
+                        ]),
+                    },
+                ),
+                ("depends_on", Schema::Array(&Schema::String)),
+                ("image", Schema::String),
+            ]),
+        },
+    ),
+    (
+        "pipelines",
+        Schema::Map {
+            key_check: None,
+            values: &Schema::Table(&[
+                ("description", Schema::String),
+                (
+                    "steps",
+                    Schema::Array(&Schema::Table(&[
+                        ("pipe", Schema::String),
+                        ("profile", Schema::String),
+                        ("local", Schema::Bool),
+                        ("container", Schema::String),
+                    ])),
+                ),
+            ]),
+        },
+    ),
+]);
+
+/// A single problem found in a configuration file
+#[derive(Debug, Clone)]
+pub struct ConfigIssue {
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl Display for ConfigIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            self.file.to_string_lossy(),
+            self.line,
+            self.column,
+            self.message
+        )
+    }
+}
+
+/// Walks a parsed TOML document and collects the issues it finds.
+struct Checker<'a> {
+    content: &'a str,
+    file: &'a Path,
+    issues: Vec<ConfigIssue>,
+}
+
+impl Checker<'_> {
+    /// Record an issue at some byte span of the file
+    fn report(&mut self, span: Option<Range<usize>>, message: String) {
+        let (line, column) = line_and_column(self.content, span.map(|x| x.start).unwrap_or(0));
+        self.issues.push(ConfigIssue {
+            file: self.file.to_path_buf(),
+            line,
+            column,
+            message,
+        })
+    }
+
+    /// Check an item against the schema.
+    ///
+    /// The `dotted` path is the full name of the item, e.g. `data.paths.input`.
+    /// Tables created implicitly (like `data` in `[data.paths]`) have no
+    /// span, so the `fallback` span is used to report errors on them.
+    fn check(
+        &mut self,
+        schema: &Schema,
+        item: &Item,
+        dotted: &str,
+        fallback: Option<Range<usize>>,
+    ) {
+        let span = item.span().or(fallback);
+        match schema {
+            Schema::Table(fields) => {
+                let table = match item.as_table_like() {
+                    Some(x) => x,
+                    None => return self.wrong_type("a table", item, dotted, span),
+                };
+                for (key, value) in table.iter() {
+                    let key_span = table
+                        .get_key_value(key)
+                        .and_then(|(k, _)| k.span())
+                        .or(span.clone());
+                    let child = join_dotted(dotted, key);
+                    match fields.iter().find(|(name, _)| *name == key) {
+                        Some((_, inner)) => self.check(inner, value, &child, key_span),
+                        None => {
+                            let known: Vec<&str> = fields.iter().map(|(name, _)| *name).collect();
+                            let message = match closest_match(key, &known) {
+                                Some(guess) => format!(
+                                    "unknown key `{}` (did you mean `{}`?)",
+                                    child,
+                                    join_dotted(dotted, guess)
+                                ),
+                                None => format!(
+                                    "unknown key `{}` (expected one of: {})",
+                                    child,
+                                    known.join(", ")
+                                ),
+                            };
+                            self.report(key_span, message);
+                        }
+                    }
+                }
+            }
+            Schema::Map { key_check, values } => {
+                let table = match item.as_table_like() {
+                    Some(x) => x,
+                    None => return self.wrong_type("a table", item, dotted, span),
+                };
+                for (key, value) in table.iter() {
+                    let key_span = table
+                        .get_key_value(key)
+                        .and_then(|(k, _)| k.span())
+                        .or(span.clone());
+                    if let Some(message) = key_check.and_then(|check| check(key)) {
+                        self.report(key_span.clone(), message);
+                    }
+                    self.check(values, value, &join_dotted(dotted, key), key_span);
+                }
+            }
+            Schema::Mixed {
+                fields,
+                values,
+                hint,
+            } => {
+                let table = match item.as_table_like() {
+                    Some(x) => x,
+                    None => return self.wrong_type("a table", item, dotted, span),
+                };
+                for (key, value) in table.iter() {
+                    let key_span = table
+                        .get_key_value(key)
+                        .and_then(|(k, _)| k.span())
+                        .or(span.clone());
+                    let inner = match fields.iter().find(|(name, _)| *name == key) {
+                        Some((_, inner)) => inner,
+                        None => values,
+                    };
+                    let issues = self.issues.len();
+                    self.check(inner, value, &join_dotted(dotted, key), key_span);
+                    let fits_values = values.is_string() && value.is_str();
+                    if self.issues.len() > issues && !std::ptr::eq(inner, *values) && fits_values {
+                        let issue = self.issues.last_mut().unwrap();
+                        issue.message = format!("{} ({})", issue.message, hint);
+                    }
+                }
+            }
+            Schema::Array(values) => {
+                // Arrays of tables, like `[[section.key]]`, are not arrays
+                // of values for toml_edit
+                if let Some(tables) = item.as_array_of_tables() {
+                    for (i, table) in tables.iter().enumerate() {
+                        let table_span = table.span().or(span.clone());
+                        self.check(
+                            values,
+                            &Item::Table(table.clone()),
+                            &format!("{}[{}]", dotted, i),
+                            table_span,
+                        );
+                    }
+                    return;
+                }
+                let array = match item.as_array() {
+                    Some(x) => x,
+                    None => return self.wrong_type("an array", item, dotted, span),
+                };
+                for (i, value) in array.iter().enumerate() {
+                    let value_span = value.span().or(span.clone());
+                    self.check(
+                        values,
+                        &Item::Value(value.clone()),
+                        &format!("{}[{}]", dotted, i),
+                        value_span,
+                    );
+                }
+            }
+            Schema::String => {
+                if item.as_str().is_none() {
+                    self.wrong_type("a string", item, dotted, span)
+                }
+            }
+            Schema::Command => match item.as_str() {
+                None => self.wrong_type("a string", item, dotted, span),
+                Some(value) => {
+                    if value.trim().is_empty() {
+                        self.report(span, format!("the command for `{}` is empty", dotted))
+                    }
+                }
+            },
+            Schema::Bool => {
+                if item.as_bool().is_none() {
+                    self.wrong_type("a boolean", item, dotted, span)
+                }
+            }
+            Schema::Scalar => {
+                let is_scalar = item
+                    .as_value()
+                    .is_some_and(|x| x.is_str() || x.is_integer() || x.is_float() || x.is_bool());
+                if !is_scalar {
+                    self.wrong_type("a string, number or boolean", item, dotted, span)
+                }
+            }
+            Schema::Choice(choices) => match item.as_str() {
+                None => self.wrong_type("a string", item, dotted, span),
+                Some(value) => {
+                    if !choices.contains(&value) {
+                        self.report(
+                            span,
+                            format!(
+                                "invalid value {:?} for `{}` (expected one of: {})",
+                                value,
+                                dotted,
+                                choices.join(", ")
+                            ),
+                        )
+                    }
+                }
+            },
+        }
+    }
+
+    fn wrong_type(
+        &mut self,
+        expected: &str,
+        item: &Item,
+        dotted: &str,
+        span: Option<Range<usize>>,
+    ) {
+        self.report(
+            span,
+            format!(
+                "expected {} for `{}`, found {}",
+                expected,
+                dotted,
+                item.type_name()
+            ),
+        )
+    }
+}
+
+/// Join a key to its parent, e.g. `data` and `paths` to `data.paths`
+///
+/// Keys that are not bare TOML keys (e.g. `"input.txt"`) are quoted.
+pub fn join_dotted(parent: &str, key: &str) -> String {
+    let is_bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|x| x.is_ascii_alphanumeric() || x == '_' || x == '-');
+    let key = if is_bare {
+        key.to_string()
+    } else {
+        format!("{:?}", key)
+    };
+
+    if parent.is_empty() {
+        key
+    } else {
+        format!("{}.{}", parent, key)
+    }
+}
+
+/// Convert a byte offset in some text to a (1-based) line and column
+fn line_and_column(content: &str, offset: usize) -> (usize, usize) {
+    let offset = offset.min(content.len());
+    let before = &content[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map(|x| x + 1).unwrap_or(0);
+
+    (line, before[line_start..].chars().count() + 1)
+}
+
+/// Find the candidate most similar to `key`, if any is close enough to be
+/// a plausible typo.
+fn closest_match<'a>(key: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    candidates
+        .iter()
+        .map(|candidate| (edit_distance(key, candidate), *candidate))
+        .filter(|(distance, candidate)| *distance <= (candidate.len() / 3).max(1))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// The Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, char_a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, char_b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(char_a != *char_b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+/// Validate the content of a kerblam.toml file.
+///
+/// Returns a list of all the issues found in the file: syntax errors, unknown
+/// keys, values of the wrong type and invalid remote URLs.
+/// The `file` is only used to label the issues.
+pub fn validate_kerblam_toml(content: &str, file: impl AsRef<Path>) -> Vec<ConfigIssue> {
+    let mut checker = Checker {
+        content,
+        file: file.as_ref(),
+        issues: vec![],
+    };
+
+    match ImDocument::parse(content) {
+        Ok(document) => {
+            checker.check(&KERBLAM_TOML_SCHEMA, document.as_item(), "", None);
+        }
+        Err(e) => checker.report(e.span(), e.message().trim().to_string()),
+    }
+
+    checker.issues
+}
+
+#[test]
+fn test_valid_toml_has_no_issues() {
+    let content = r#"
+[meta]
+version = "1.2.1"
+
+[data.paths]
+input = "data/in"
+
+[data.profiles.test]
+"input.txt" = "test_input.txt"
+
+[data.profiles.test_small]
+extends = "test"
+remove = ["input.txt"]
+
+[data.profiles.test_small.paths]
+output = "data/out/small"
+
+[data.profiles.test_small.files]
+env = "small_env"
+
+[data.remote]
+"https://example.com/file.txt" = "file.txt"
+"_" = "manual.txt"
+
+[execution]
+backend = "podman"
+
+[pipelines.full]
+steps = [{ pipe = "preprocess" }, { pipe = "analyze", profile = "test", local = true }]
+"#;
+    let issues = validate_kerblam_toml(content, "kerblam.toml");
+    assert!(issues.is_empty(), "{:?}", issues);
+}
+
+#[test]
+fn test_invalid_toml_issues() {
+    let content = r#"[code]
+pipe_dir = "src/pipes"
+
+[data.paths]
+input = 12
+
+[data.remote]
+"not a url" = "file.txt"
+
+[execution]
+backend = "docker-compose"
+
+[execution.interpreters]
+py = " "
+
+[data.profiles.test]
+env = "test_env"
+"#;
+    let issues = validate_kerblam_toml(content, "kerblam.toml");
+    let messages: Vec<String> = issues.iter().map(|x| x.to_string()).collect();
+
+    assert_eq!(messages.len(), 6, "{:?}", messages);
+    assert!(messages[0].starts_with(
+        "kerblam.toml:2:1: unknown key `code.pipe_dir` (did you mean `code.pipes_dir`?)"
+    ));
+    assert!(messages[1].starts_with("kerblam.toml:5:9: expected a string for `data.paths.input`"));
+    assert!(messages[2].starts_with("kerblam.toml:8:1: invalid URL \"not a url\""));
+    assert!(messages[3].ends_with(
+        "(to profile a file with this name, put it in the `files` table of the profile)"
+    ));
+    assert!(messages[4].starts_with("kerblam.toml:11:11: invalid value \"docker-compose\""));
+    assert!(messages[5]
+        .starts_with("kerblam.toml:14:6: the command for `execution.interpreters.py` is empty"));
+}
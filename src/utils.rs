@@ -1,6 +1,7 @@
-use anyhow::{anyhow, bail, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use filetime::{set_file_mtime, FileTime};
 use flate2::Compression;
+use std::collections::BTreeMap;
 use std::ffi::{OsStr, OsString};
 use std::fs::File;
 use std::fs::{self, create_dir_all};
@@ -11,7 +12,7 @@ use std::str::FromStr;
 use termimad::{minimad, MadSkin};
 
 use rand::distributions::{Alphanumeric, DistString};
-use version_compare::Version;
+use semver::{Version, VersionReq};
 use walkdir::{self, DirEntry};
 
 use crate::options::{KerblamTomlOptions, Pipe};
@@ -289,33 +290,101 @@ pub fn find_dirs(inspected_path: impl AsRef<Path>, filters: Option<Vec<PathBuf>>
     find_path_items_with_filter(inspected_path, |x| x.metadata().unwrap().is_dir(), filters)
 }
 
-/// Warn the user if there is a version mismatch between the config and the real version
-pub fn warn_kerblam_version(config: &KerblamTomlOptions) {
-    // TODO: is there a way to avoid this clone()? I feel like there should be
-    // but I'm not sure.
-    let version = config.clone().meta.and_then(|x| x.version);
-    let current_ver = Version::from(VERSION);
-
-    let version = match version {
-        None => return,
-        Some(ver) => ver,
+/// Parse a version, like `1.2.1`
+///
+/// Short versions (like `1.2` or `1`) are accepted, and padded with zeros.
+pub fn parse_version(version: &str) -> Result<Version> {
+    let version = version.trim();
+    let parts: Vec<&str> = version.split('.').collect();
+    let is_short = parts.len() < 3
+        && parts
+            .iter()
+            .all(|x| !x.is_empty() && x.chars().all(|x| x.is_ascii_digit()));
+    let full = if is_short {
+        let mut parts = parts.clone();
+        parts.resize(3, "0");
+        parts.join(".")
+    } else {
+        version.to_string()
     };
 
-    let version = match Version::from(&version) {
+    Version::parse(&full).with_context(|| format!("Invalid version {:?}", version))
+}
+
+/// Check that this version of Kerblam! can run the project
+///
+/// If the config has a `meta.requires` semver requirement (like ">=1.2, <2"),
+/// fails if this version of Kerblam! does not satisfy it.
+/// Otherwise, warns the user if the `meta.version` of the config has a
+/// different major or minor version: patch releases are compatible.
+pub fn warn_kerblam_version(config: &KerblamTomlOptions) -> Result<()> {
+    let current_ver = Version::parse(VERSION)?;
+    let meta = match &config.meta {
         Some(x) => x,
-        None => return,
+        None => return Ok(()),
     };
 
-    let current_ver = match current_ver {
+    if let Some(requires) = &meta.requires {
+        let requirement = VersionReq::parse(requires)
+            .with_context(|| format!("Invalid `meta.requires` requirement {:?}", requires))?;
+        if !requirement.matches(&current_ver) {
+            bail!(
+                "This project requires Kerblam! {requirement}, but this is Kerblam! {current_ver}. Please install a compatible version."
+            )
+        }
+        return Ok(());
+    }
+
+    let version = match &meta.version {
         Some(x) => x,
-        None => return,
+        None => return Ok(()),
+    };
+    let version = match parse_version(version) {
+        Ok(x) => x,
+        Err(_) => {
+            println!(
+                "⚠️  TOML version ({version}) is not a valid version, so it cannot be checked!"
+            );
+            return Ok(());
+        }
     };
 
-    if version != current_ver {
+    if (version.major, version.minor) != (current_ver.major, current_ver.minor) {
         println!(
             "⚠️  TOML version ({version}) is different from this kerblam version ({current_ver})!",
         )
     };
+
+    Ok(())
+}
+
+/// Make the list of pipes shown to the user, grouped by namespace
+///
+/// Pipes that are not namespaced are shown first.
+fn list_pipes(pipes: &[Pipe]) -> String {
+    let mut groups: BTreeMap<Option<String>, Vec<String>> = BTreeMap::new();
+    for pipe in pipes {
+        groups
+            .entry(pipe.namespace())
+            .or_default()
+            .push(pipe.to_string());
+    }
+
+    let mut lines: Vec<String> = vec![];
+    for (namespace, mut group) in groups {
+        group.sort_unstable();
+        // The sorting starts with the emojis, so we sort in the opposite
+        // way to show the non-missing (e.g. not "◾") emojis to the top of the
+        // list. These are generally the most "interesting" pipelines.
+        group.reverse();
+
+        if let Some(namespace) = namespace {
+            lines.push(format!("  📁 {}/", namespace));
+        }
+        lines.extend(group);
+    }
+
+    lines.join("\n")
 }
 
 /// Find a pipe by name or die trying
@@ -325,16 +394,8 @@ pub fn warn_kerblam_version(config: &KerblamTomlOptions) {
 ///
 /// Errors if no pipe in found.
 pub fn find_pipe_by_name(config: &KerblamTomlOptions, pipe_name: Option<String>) -> Result<Pipe> {
-    let pipes = config.pipes();
-    let mut pipes_list = pipes.iter().map(|x| x.to_string()).collect::<Vec<String>>();
-
-    pipes_list.sort_unstable();
-    // The sorting starts with the emojis, so we sort in the opposite
-    // way to show the non-missing (e.g. not "◾") emojis to the top of the
-    // list. These are generally the most "interesting" pipelines.
-    pipes_list.reverse();
-
-    let pipes_list = pipes_list.join("\n");
+    let pipes = config.pipes()?;
+    let pipes_list = list_pipes(&pipes);
     let profiles: Option<Vec<String>> = config
         .clone()
         .data
@@ -345,25 +406,32 @@ pub fn find_pipe_by_name(config: &KerblamTomlOptions, pipe_name: Option<String>)
         Some(list) => list.join(", "),
     };
 
+    let mut pipelines: Vec<&String> = config.pipelines.keys().collect();
+    pipelines.sort_unstable();
+    let pipelines_list = if pipelines.is_empty() {
+        String::new()
+    } else {
+        format!(
+            "\nAvailable pipelines: {}.",
+            pipelines
+                .iter()
+                .map(|x| x.as_str())
+                .collect::<Vec<&str>>()
+                .join(", ")
+        )
+    };
+
     let pipe_name = match pipe_name {
         None => bail!(
-            "No runtime specified. Available runtimes:\n{}\nAvailable profiles: {}.",
+            "No runtime specified. Available runtimes:\n{}\nAvailable profiles: {}.{}",
             pipes_list,
-            profiles_list
+            profiles_list,
+            pipelines_list
         ),
         Some(name) => name,
     };
 
-    let pipe = {
-        let mut hit: Option<Pipe> = None;
-        for pipe in pipes {
-            if pipe.name() == pipe_name {
-                hit = Some(pipe.clone())
-            }
-        }
-
-        hit
-    };
+    let pipe = pipes.into_iter().find(|x| x.name() == pipe_name);
 
     let pipe = match pipe {
         None => bail!(
@@ -536,7 +604,6 @@ pub fn push_fragment(buffer: impl AsRef<Path>, ext: &str) -> PathBuf {
 }
 
 /// Get a random alphanumerical string some characters long
-#[allow(dead_code)]
 pub fn get_salt(length: usize) -> String {
     Alphanumeric.sample_string(&mut rand::thread_rng(), length)
 }